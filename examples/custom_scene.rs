@@ -0,0 +1,67 @@
+// Build a small scene by hand, render it and save it as a PNG
+//
+// cargo run --release --example custom_scene
+
+use pathtracer::image;
+use pathtracer::{
+    render, BvhNode, Camera, Dielectric, Gradient, ImageFormat, Lambertian, Lights, Metal,
+    RenderSettings, Rng, Sphere, Tonemap, Tonemapper, Vector3, World,
+};
+use std::sync::Arc;
+
+fn main() {
+    let (width, height) = (400, 225);
+    let mut rng = Rng::new(7);
+
+    let mut world = World::new();
+    world.add(Sphere::new(
+        Vector3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::new(Vector3::new(0.5, 0.5, 0.5))),
+    ));
+    world.add(Sphere::new(
+        Vector3::new(-2.2, 1.0, 0.0),
+        1.0,
+        Arc::new(Lambertian::new(Vector3::new(0.7, 0.2, 0.1))),
+    ));
+    world.add(Sphere::new(
+        Vector3::new(0.0, 1.0, 0.0),
+        1.0,
+        Arc::new(Dielectric::new(1.5)),
+    ));
+    world.add(Sphere::new(
+        Vector3::new(2.2, 1.0, 0.0),
+        1.0,
+        Arc::new(Metal::new(Vector3::new(0.8, 0.8, 0.9), 0.05)),
+    ));
+
+    let eye = Vector3::new(0.0, 2.0, 8.0);
+    let center = Vector3::new(0.0, 1.0, 0.0);
+    let camera = Camera::new(
+        eye,
+        center,
+        Vector3::unit_y(),
+        40.0,
+        width as f32 / height as f32,
+        0.05,
+        (eye - center).length(),
+        0.0,
+        1.0,
+    );
+
+    let bvh = BvhNode::new(world.hits, 0.0, 1.0, &mut rng);
+    let lights = Lights::new(Box::new(Gradient::sky()));
+
+    let settings = RenderSettings {
+        seed: 7,
+        ..RenderSettings::new(width, height, 64)
+    };
+    let radiance = render(&bvh, &lights, &camera, &settings);
+
+    let tonemap = Tonemap {
+        tonemapper: Tonemapper::Aces,
+        ..Tonemap::default()
+    };
+    image::save("custom_scene.png", &radiance, ImageFormat::Png, &tonemap)
+        .expect("cannot write custom_scene.png");
+}
@@ -0,0 +1,36 @@
+// Render a bundled scene at a few samples per pixel and denoise it with its AOVs
+//
+// cargo run --release --example denoised_preview
+
+use pathtracer::aov::render_aovs;
+use pathtracer::denoise::{denoise, DenoiseSettings};
+use pathtracer::image;
+use pathtracer::scenes::find_scene;
+use pathtracer::{render, ImageFormat, RenderSettings, Rng, Tonemap};
+
+fn main() {
+    let (width, height) = (400, 300);
+    let scene = find_scene("basic").expect("the basic scene is bundled");
+    let (world, camera, lights) = (scene.build)(width, height, (0.0, 1.0), &mut Rng::new(0));
+
+    let settings = RenderSettings::new(width, height, 8);
+    let noisy = render(world.as_ref(), &lights, &camera, &settings);
+
+    let aovs = render_aovs(world.as_ref(), &lights, &camera, &settings);
+    let denoise_settings = DenoiseSettings {
+        threads: settings.threads,
+        ..DenoiseSettings::default()
+    };
+    let denoised = denoise(&noisy, &aovs.guides(), &denoise_settings);
+
+    let tonemap = Tonemap::default();
+    image::save("preview_noisy.png", &noisy, ImageFormat::Png, &tonemap)
+        .expect("cannot write preview_noisy.png");
+    image::save(
+        "preview_denoised.png",
+        &denoised,
+        ImageFormat::Png,
+        &tonemap,
+    )
+    .expect("cannot write preview_denoised.png");
+}
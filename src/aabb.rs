@@ -1,13 +1,20 @@
 use crate::ray::Ray;
 use crate::vector::Vector3;
-use std::cmp;
 
 pub fn min(a: f32, b: f32) -> f32 {
-    return if a < b { a } else { b };
+    if a < b {
+        a
+    } else {
+        b
+    }
 }
 
 pub fn max(a: f32, b: f32) -> f32 {
-    return if a > b { a } else { b };
+    if a > b {
+        a
+    } else {
+        b
+    }
 }
 
 #[derive(Debug, Default, Copy, Clone)]
@@ -21,22 +28,21 @@ impl Aabb {
         Aabb { min, max }
     }
 
-    pub fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> bool {
+    pub fn hit(&self, ray: Ray, mut t_min: f32, mut t_max: f32) -> bool {
         for a in 0..3 {
             let inv_d = 1.0 / ray.direction[a];
             let mut t0 = (self.min[a] - ray.origin[a]) * inv_d;
             let mut t1 = (self.max[a] - ray.origin[a]) * inv_d;
 
             if inv_d < 0.0 {
-                let temp = t0;
-                t0 = t1;
-                t1 = temp;
+                std::mem::swap(&mut t0, &mut t1);
             }
 
-            let min = if t0 > t_min { t0 } else { t_min };
-            let max = if t1 < t_min { t1 } else { t_max };
+            // Narrow the interval slab by slab
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
 
-            if max <= min {
+            if t_max <= t_min {
                 return false;
             }
         }
@@ -0,0 +1,114 @@
+use crate::camera::Camera;
+use crate::hit::Hit;
+use crate::image::Framebuffer;
+use crate::light::Lights;
+use crate::render::{render_samples, Accumulator, RenderSettings, Tile};
+use crate::stats::Counters;
+use crate::tonemap::Transfer;
+use crate::vector::Vector3;
+
+#[derive(Debug, Copy, Clone)]
+pub struct AdaptiveSettings {
+    // Error below which a pixel is considered converged, as returned by pixel_error: the
+    // standard error of its luminance after the sRGB curve, where 1 is white
+    pub threshold: f32,
+    // Samples every pixel takes before its error is estimated
+    pub min_samples: u32,
+    // Samples a pixel may never exceed
+    pub max_samples: u32,
+    // Samples added to every unconverged pixel per pass
+    pub pass_samples: u32,
+}
+
+// Standard error of a pixel's mean once encoded for display
+// The output's tonemapping is unknown here, so the error is carried through the sRGB
+// curve alone, stderr times its slope at the mean: dark pixels need less absolute error
+pub fn pixel_error(accumulator: &Accumulator, x: usize, y: usize) -> f32 {
+    let n = accumulator.samples.get(x, y) as f32;
+    if n < 2.0 {
+        return f32::INFINITY;
+    }
+
+    let mean = accumulator.luminance.get(x, y) / n;
+    let stderr = (accumulator.variance(x, y) / n).sqrt();
+
+    stderr * Transfer::Srgb.slope(mean)
+}
+
+// Samples each pixel gets in the next pass, zero once it converged or hit the maximum
+// Pixels outside the rendered region get no samples
+pub fn next_budget(
+    accumulator: &Accumulator,
+    adaptive: &AdaptiveSettings,
+    region: Tile,
+) -> Framebuffer<u32> {
+    let mut budget = Framebuffer::new(accumulator.width(), accumulator.height());
+
+    for y in region.y0..region.y1 {
+        for x in region.x0..region.x1 {
+            let taken = accumulator.samples.get(x, y);
+
+            let wanted = if taken < adaptive.min_samples {
+                adaptive.min_samples - taken
+            } else if pixel_error(accumulator, x, y) > adaptive.threshold {
+                adaptive.pass_samples.max(1)
+            } else {
+                0
+            };
+
+            budget.set(x, y, wanted.min(adaptive.max_samples.saturating_sub(taken)));
+        }
+    }
+
+    budget
+}
+
+// Keep sampling unconverged pixels until all of them are below the threshold or at the
+// maximum sample count, calling on_pass after each pass with the number of active pixels
+// Returns the work counters of all passes
+pub fn render_adaptive<F, E>(
+    world: &dyn Hit,
+    lights: &Lights,
+    camera: &Camera,
+    settings: &RenderSettings,
+    accumulator: &mut Accumulator,
+    adaptive: &AdaptiveSettings,
+    mut on_pass: F,
+) -> Result<Counters, E>
+where
+    F: FnMut(&Accumulator, usize) -> Result<(), E>,
+{
+    let mut counters = Counters::default();
+
+    loop {
+        let budget = next_budget(accumulator, adaptive, settings.region());
+
+        let active = budget.pixels().iter().filter(|&&n| n > 0).count();
+        if active == 0 {
+            return Ok(counters);
+        }
+
+        counters += render_samples(world, lights, camera, settings, accumulator, &budget);
+
+        on_pass(accumulator, active)?;
+    }
+}
+
+// Map a value in [0, 1] from dark blue through green to red
+fn heat(t: f32) -> Vector3 {
+    let t = t.clamp(0.0, 1.0);
+
+    let r = (1.5 - (4.0 * t - 3.0).abs()).clamp(0.0, 1.0);
+    let g = (1.5 - (4.0 * t - 2.0).abs()).clamp(0.0, 1.0);
+    let b = (1.5 - (4.0 * t - 1.0).abs()).clamp(0.0, 1.0);
+
+    Vector3::new(r, g, b)
+}
+
+// False color image of the sample count of every pixel, relative to max_samples
+// These are display values, to be saved without tonemapping
+pub fn sample_heatmap(accumulator: &Accumulator, max_samples: u32) -> Framebuffer<Vector3> {
+    accumulator
+        .samples
+        .map(|n| heat(n as f32 / max_samples.max(1) as f32))
+}
@@ -0,0 +1,141 @@
+use crate::aabb::{surrounding_box, Aabb};
+use crate::hit::{Hit, HitRecord};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vector::Vector3;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+// Frame range and timing of an image sequence
+// Frame n starts n / fps seconds into the animation
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Animation {
+    pub first_frame: u32,
+    pub last_frame: u32,
+    pub fps: f32,
+    // Fraction of a frame the shutter is open for, in degrees, 360 blurs over the whole frame
+    pub shutter_angle: f32,
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Animation {
+            first_frame: 0,
+            last_frame: 0,
+            fps: 24.0,
+            shutter_angle: 180.0,
+        }
+    }
+}
+
+impl Animation {
+    pub fn frames(&self) -> RangeInclusive<u32> {
+        self.first_frame..=self.last_frame
+    }
+
+    // Times the shutter opens and closes during a frame, in seconds
+    pub fn shutter(&self, frame: u32) -> (f32, f32) {
+        let open = frame as f32 / self.fps;
+        (open, open + self.shutter_angle / 360.0 / self.fps)
+    }
+}
+
+// Number an output path for a frame, test.png becomes test_0001.png
+pub fn frame_path(path: &str, frame: u32) -> String {
+    let path = Path::new(path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}_{:04}.{}", stem, frame, extension.to_string_lossy()),
+        None => format!("{}_{:04}", stem, frame),
+    };
+
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+// A path through space given by positions at points in time, moving in straight lines
+// between them and resting at the first and last
+#[derive(Debug, Clone)]
+pub struct Keyframes {
+    keys: Vec<(f32, Vector3)>,
+}
+
+impl Keyframes {
+    pub fn new(mut keys: Vec<(f32, Vector3)>) -> Self {
+        assert!(!keys.is_empty(), "a path needs at least one keyframe");
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Keyframes { keys }
+    }
+
+    pub fn position(&self, time: f32) -> Vector3 {
+        let next = self.keys.partition_point(|&(t, _)| t <= time);
+        if next == 0 {
+            return self.keys[0].1;
+        }
+        if next == self.keys.len() {
+            return self.keys[next - 1].1;
+        }
+
+        let (t0, p0) = self.keys[next - 1];
+        let (t1, p1) = self.keys[next];
+        p0 + (p1 - p0) * ((time - t0) / (t1 - t0))
+    }
+
+    // Box around every position between two times, the ends and the keyframes between them
+    pub fn bounds(&self, t0: f32, t1: f32) -> Aabb {
+        let (p0, p1) = (self.position(t0), self.position(t1));
+        let mut bounds = surrounding_box(Aabb::new(p0, p0), Aabb::new(p1, p1));
+
+        for &(_, p) in self.keys.iter().filter(|&&(t, _)| t > t0 && t < t1) {
+            bounds = surrounding_box(bounds, Aabb::new(p, p));
+        }
+
+        bounds
+    }
+}
+
+// An object moved along a path, its own coordinates offset by the path's position at
+// the time of each ray
+pub struct Animated<H> {
+    object: H,
+    path: Keyframes,
+}
+
+impl<H: Hit> Animated<H> {
+    pub fn new(object: H, path: Keyframes) -> Self {
+        Animated { object, path }
+    }
+}
+
+impl<H: Hit> Hit for Animated<H> {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let offset = self.path.position(ray.time);
+        let moved = Ray::with_time(ray.origin - offset, ray.direction, ray.time);
+
+        self.object.hit(moved, t_min, t_max).map(|mut record| {
+            record.p += offset;
+            record
+        })
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<Aabb> {
+        let object = self.object.bounding_box(t0, t1)?;
+        let path = self.path.bounds(t0, t1);
+
+        Some(Aabb::new(object.min + path.min, object.max + path.max))
+    }
+
+    fn sample_direction(
+        &self,
+        origin: Vector3,
+        time: f32,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Vector3, f32)> {
+        let offset = self.path.position(time);
+        self.object.sample_direction(origin - offset, time, sampler)
+    }
+
+    fn direction_pdf(&self, origin: Vector3, direction: Vector3, time: f32) -> f32 {
+        let offset = self.path.position(time);
+        self.object.direction_pdf(origin - offset, direction, time)
+    }
+}
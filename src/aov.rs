@@ -0,0 +1,310 @@
+use crate::camera::Camera;
+use crate::denoise::Guides;
+use crate::hit::Hit;
+use crate::image::{self, write_exr_channels, Channel, Framebuffer, ImageFormat};
+use crate::light::Lights;
+use crate::random::Rng;
+use crate::ray::Ray;
+use crate::render::{pixel_ray, render_tiles, RenderSettings, Tile};
+use crate::sampler::Sampler;
+use crate::tonemap::Tonemap;
+use crate::vector::Vector3;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::str::FromStr;
+
+// First hits converge much faster than radiance, a few samples per pixel are enough
+const MAX_SAMPLES: u32 = 16;
+
+// Arbitrary output variables, auxiliary images written next to the radiance
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Aov {
+    // Shading normal at the first hit
+    Normal,
+    // Distance along the camera ray to the first hit
+    Depth,
+    // Color of the first hit's material, the sky color for rays that escape
+    Albedo,
+    // World space position of the first hit
+    Position,
+    // Number of the first object hit, 0 for the background
+    ObjectId,
+}
+
+impl Aov {
+    pub const NAMES: &'static [&'static str] = &["normal", "depth", "albedo", "position", "id"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::Albedo => "albedo",
+            Aov::Position => "position",
+            Aov::ObjectId => "id",
+        }
+    }
+}
+
+impl FromStr for Aov {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normal" => Ok(Aov::Normal),
+            "depth" => Ok(Aov::Depth),
+            "albedo" => Ok(Aov::Albedo),
+            "position" => Ok(Aov::Position),
+            "id" => Ok(Aov::ObjectId),
+            _ => Err(()),
+        }
+    }
+}
+
+// First hit data of a single pixel
+#[derive(Debug, Default, Copy, Clone)]
+struct AovPixel {
+    normal: Vector3,
+    depth: f32,
+    albedo: Vector3,
+    position: Vector3,
+    object_id: u32,
+}
+
+// Every AOV of an image
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aovs {
+    pub normal: Framebuffer<Vector3>,
+    pub depth: Framebuffer<f32>,
+    pub albedo: Framebuffer<Vector3>,
+    pub position: Framebuffer<Vector3>,
+    pub object_id: Framebuffer<u32>,
+}
+
+// Average the first hits of a pixel's samples, using the same camera rays as the
+// first samples of the radiance
+// Depth and position average only the samples that hit something, the object ID is
+// that of the first sample since IDs cannot be blended
+fn render_pixel(
+    world: &dyn Hit,
+    lights: &Lights,
+    camera: &Camera,
+    settings: &RenderSettings,
+    (x, y): (usize, usize),
+    sampler: &mut dyn Sampler,
+) -> AovPixel {
+    let samples = (settings.samples as u32).clamp(1, MAX_SAMPLES);
+
+    let mut result = AovPixel::default();
+    let mut hits = 0;
+
+    for sample in 0..samples {
+        sampler.start_sample((x, y), sample);
+        let ray = pixel_ray(camera, settings, (x, y), sampler);
+
+        // A unit direction makes t the distance to the hit
+        let ray = Ray::with_time(ray.origin, ray.direction.normalize(), ray.time);
+
+        match world.hit(ray, 0.0001, f32::MAX) {
+            Some(record) => {
+                result.normal += record.normal;
+                result.depth += record.t;
+                result.albedo += record.material.albedo(&record);
+                result.position += record.p;
+                hits += 1;
+
+                if sample == 0 {
+                    result.object_id = record.object_id;
+                }
+            }
+            None => result.albedo += lights.environment.radiance(ray.direction),
+        }
+    }
+
+    result.normal /= samples as f32;
+    result.albedo /= samples as f32;
+
+    if hits > 0 {
+        result.depth /= hits as f32;
+        result.position /= hits as f32;
+    }
+
+    result
+}
+
+// Render every AOV, a cheap pass that only follows camera rays to their first hit
+pub fn render_aovs(
+    world: &dyn Hit,
+    lights: &Lights,
+    camera: &Camera,
+    settings: &RenderSettings,
+) -> Aovs {
+    let (results, _) = render_tiles(settings, |tile| {
+        let mut sampler = settings.sampler.create(settings.seed, settings.samples);
+        let mut pixels = Vec::with_capacity((tile.x1 - tile.x0) * (tile.y1 - tile.y0));
+        for y in tile.y0..tile.y1 {
+            for x in tile.x0..tile.x1 {
+                pixels.push(render_pixel(
+                    world,
+                    lights,
+                    camera,
+                    settings,
+                    (x, y),
+                    sampler.as_mut(),
+                ));
+            }
+        }
+
+        pixels
+    });
+
+    let mut image = Framebuffer::new(settings.width, settings.height);
+    for (tile, pixels) in results {
+        let mut pixels = pixels.into_iter();
+        for y in tile.y0..tile.y1 {
+            for x in tile.x0..tile.x1 {
+                image.set(x, y, pixels.next().unwrap());
+            }
+        }
+    }
+
+    Aovs {
+        normal: image.map(|p| p.normal),
+        depth: image.map(|p| p.depth),
+        albedo: image.map(|p| p.albedo),
+        position: image.map(|p| p.position),
+        object_id: image.map(|p| p.object_id),
+    }
+}
+
+// Scale every channel to [0, 1] over the range of values in the image
+fn normalize(image: &Framebuffer<Vector3>) -> Framebuffer<Vector3> {
+    let mut low = Vector3::new(f32::MAX, f32::MAX, f32::MAX);
+    let mut high = Vector3::new(f32::MIN, f32::MIN, f32::MIN);
+    for p in image.pixels() {
+        for i in 0..3 {
+            low[i] = low[i].min(p[i]);
+            high[i] = high[i].max(p[i]);
+        }
+    }
+
+    image.map(|p| {
+        let mut result = Vector3::default();
+        for i in 0..3 {
+            if high[i] > low[i] {
+                result[i] = (p[i] - low[i]) / (high[i] - low[i]);
+            }
+        }
+
+        result
+    })
+}
+
+// A distinct color for every object, black for the background
+fn id_color(id: u32) -> Vector3 {
+    if id == 0 {
+        return Vector3::default();
+    }
+
+    let mut rng = Rng::new(id as u64);
+    Vector3::new(rng.float(), rng.float(), rng.float())
+}
+
+impl Aovs {
+    // The AOVs the denoiser is steered by
+    pub fn guides(&self) -> Guides<'_> {
+        Guides {
+            albedo: Some(&self.albedo),
+            normal: Some(&self.normal),
+            depth: Some(&self.depth),
+        }
+    }
+
+    // Only the pixels of a region, to go with a cropped image
+    pub fn crop(&self, region: Tile) -> Aovs {
+        let origin = (region.x0, region.y0);
+        let (width, height) = (region.width(), region.height());
+
+        Aovs {
+            normal: self.normal.crop(origin, width, height),
+            depth: self.depth.crop(origin, width, height),
+            albedo: self.albedo.crop(origin, width, height),
+            position: self.position.crop(origin, width, height),
+            object_id: self.object_id.crop(origin, width, height),
+        }
+    }
+
+    // Raw values of an AOV, single channel AOVs are repeated in all three
+    pub fn linear(&self, aov: Aov) -> Framebuffer<Vector3> {
+        match aov {
+            Aov::Normal => self.normal.clone(),
+            Aov::Depth => self.depth.map(|d| Vector3::new(d, d, d)),
+            Aov::Albedo => self.albedo.clone(),
+            Aov::Position => self.position.clone(),
+            Aov::ObjectId => self.object_id.map(|id| {
+                let id = id as f32;
+                Vector3::new(id, id, id)
+            }),
+        }
+    }
+
+    // Display values of an AOV for 8 bit formats
+    pub fn display(&self, aov: Aov) -> Framebuffer<Vector3> {
+        match aov {
+            Aov::Normal => self.normal.map(|n| {
+                if n == Vector3::default() {
+                    n
+                } else {
+                    n * 0.5 + Vector3::new(0.5, 0.5, 0.5)
+                }
+            }),
+            Aov::Depth => normalize(&self.linear(Aov::Depth)),
+            Aov::Albedo => Tonemap::default().apply_image(&self.albedo),
+            Aov::Position => normalize(&self.position),
+            Aov::ObjectId => self.object_id.map(id_color),
+        }
+    }
+
+    // Channels of an AOV as a layer of a multi-channel image
+    pub fn channels(&self, aov: Aov) -> Vec<Channel> {
+        match aov {
+            Aov::Normal => Channel::split("normal", ["X", "Y", "Z"], &self.normal),
+            Aov::Albedo => Channel::split("albedo", ["R", "G", "B"], &self.albedo),
+            Aov::Position => Channel::split("position", ["X", "Y", "Z"], &self.position),
+            Aov::Depth => vec![Channel {
+                name: "depth.Z".to_string(),
+                values: self.depth.clone(),
+            }],
+            Aov::ObjectId => vec![Channel {
+                name: "id.ID".to_string(),
+                values: self.object_id.map(|id| id as f32),
+            }],
+        }
+    }
+
+    // Write one AOV as an image of its own
+    pub fn save<P: AsRef<Path>>(&self, path: P, aov: Aov, format: ImageFormat) -> io::Result<()> {
+        let values = if format.is_hdr() {
+            self.linear(aov)
+        } else {
+            self.display(aov)
+        };
+
+        image::save(path, &values, format, &Tonemap::identity())
+    }
+
+    // Write the radiance and the given AOVs as layers of one EXR file
+    pub fn save_layers<P: AsRef<Path>>(
+        &self,
+        path: P,
+        radiance: &Framebuffer<Vector3>,
+        layers: &[Aov],
+    ) -> io::Result<()> {
+        let mut channels = Channel::split("", ["R", "G", "B"], radiance);
+        for aov in layers {
+            channels.extend(self.channels(*aov));
+        }
+
+        write_exr_channels(BufWriter::new(File::create(path)?), &channels)
+    }
+}
@@ -0,0 +1,115 @@
+use crate::hit::HitRecord;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vector::Vector3;
+use std::ops::BitOr;
+
+// Kinds of scattering a material does, as a set
+// Delta lobes scatter into exact directions, which have no density and which light
+// sampling can never find
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Lobes(u8);
+
+impl Lobes {
+    pub const NONE: Lobes = Lobes(0);
+    // Spread over the whole hemisphere
+    pub const DIFFUSE: Lobes = Lobes(1);
+    // Spread around a preferred direction
+    pub const GLOSSY: Lobes = Lobes(2);
+    pub const DELTA: Lobes = Lobes(4);
+
+    pub fn contains(self, other: Lobes) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    // Nothing but exact directions, or no scattering at all
+    pub fn is_delta(self) -> bool {
+        self.0 & !Lobes::DELTA.0 == 0
+    }
+}
+
+impl BitOr for Lobes {
+    type Output = Lobes;
+
+    fn bitor(self, other: Lobes) -> Lobes {
+        Lobes(self.0 | other.0)
+    }
+}
+
+// Orthonormal basis around a surface normal, the local shading frame materials work in
+// The normal is +z, directions above the surface have a positive z
+#[derive(Debug, Copy, Clone)]
+pub struct Frame {
+    s: Vector3,
+    t: Vector3,
+    n: Vector3,
+}
+
+impl Frame {
+    pub fn new(normal: Vector3) -> Self {
+        let n = normal.normalize();
+        let (s, t) = n.perpendiculars();
+        Frame { s, t, n }
+    }
+
+    pub fn to_local(&self, v: Vector3) -> Vector3 {
+        Vector3::new(v.dot(self.s), v.dot(self.t), v.dot(self.n))
+    }
+
+    pub fn to_world(&self, v: Vector3) -> Vector3 {
+        self.s * v.x + self.t * v.y + self.n * v.z
+    }
+}
+
+// A direction picked by a material, with what it scatters along it
+#[derive(Debug, Copy, Clone)]
+pub struct BsdfSample {
+    // Unit direction the light arrives from
+    pub direction: Vector3,
+    // The BSDF times the cosine, divided by the density
+    pub weight: Vector3,
+    // Density per solid angle, or the probability of the lobe for delta lobes
+    pub pdf: f32,
+    // The lobe the direction came from
+    pub lobe: Lobes,
+}
+
+// A material at a hit, seen from where the ray came from, working in world space
+pub struct Bsdf<'a> {
+    material: &'a dyn Material,
+    frame: Frame,
+    // Unit direction back along the ray, in the local frame
+    wo: Vector3,
+}
+
+impl<'a> Bsdf<'a> {
+    pub fn new(ray_in: Ray, record: &'a HitRecord) -> Self {
+        let frame = Frame::new(record.normal);
+        Bsdf {
+            material: record.material.as_ref(),
+            frame,
+            wo: frame.to_local(-ray_in.direction.normalize()),
+        }
+    }
+
+    pub fn lobes(&self) -> Lobes {
+        self.material.lobes()
+    }
+
+    pub fn sample(&self, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        let sample = self.material.sample(self.wo, sampler)?;
+        Some(BsdfSample {
+            direction: self.frame.to_world(sample.direction),
+            ..sample
+        })
+    }
+
+    pub fn eval(&self, direction: Vector3) -> Vector3 {
+        self.material.eval(self.wo, self.frame.to_local(direction))
+    }
+
+    pub fn pdf(&self, direction: Vector3) -> f32 {
+        self.material.pdf(self.wo, self.frame.to_local(direction))
+    }
+}
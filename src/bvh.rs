@@ -1,7 +1,8 @@
 use crate::hit::{Hit, HitRecord};
 use crate::aabb::Aabb;
 use crate::ray::Ray;
-use crate::random_float;
+use crate::stats::count_bvh_node;
+use crate::random::Rng;
 use std::cmp::Ordering;
 use crate::aabb::surrounding_box;
 
@@ -11,12 +12,12 @@ macro_rules! box_compare {
             let box_left = a.bounding_box(0.0, 0.0).unwrap();
             let box_right = b.bounding_box(0.0, 0.0).unwrap();
 
-            return if box_left.min.$a - box_right.min.$a < 0.0 {
-                Ordering::Less
-            }
-            else {
-                Ordering::Greater
-            }
+            // Sorting requires a total order, equal boxes must compare equal
+            box_left
+                .min
+                .$a
+                .partial_cmp(&box_right.min.$a)
+                .unwrap_or(Ordering::Equal)
         }
     };
 }
@@ -30,11 +31,11 @@ pub struct NullBvhNode {
 }
 
 impl Hit for NullBvhNode {
-    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, _ray: Ray, _t_min: f32, _t_max: f32) -> Option<HitRecord> {
         None
     }
 
-    fn bounding_box(&self, t0: f32, t1: f32) -> Option<Aabb> {
+    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<Aabb> {
         Some(self.aabb)
     }
 }
@@ -46,8 +47,11 @@ pub struct BvhNode {
 }
 
 impl BvhNode {
-    pub fn new(mut hits: Vec<Box<dyn Hit>>, time0: f32, time1: f32) -> Self {
-        let axis = (3.0 * random_float()) as i32;
+    // Panics if there are no objects, or one of them has no bounding box
+    pub fn new(mut hits: Vec<Box<dyn Hit>>, time0: f32, time1: f32, rng: &mut Rng) -> Self {
+        assert!(!hits.is_empty(), "a BVH needs at least one object");
+
+        let axis = (3.0 * rng.float()) as i32;
         if axis == 0 {
             hits.sort_by(box_x_compare);
         }
@@ -72,8 +76,8 @@ impl BvhNode {
         }
         else {
             let rest = hits.split_off(l / 2);
-            left = Box::new(BvhNode::new(hits, time0, time1));
-            right = Box::new(BvhNode::new(rest, time0, time1));
+            left = Box::new(BvhNode::new(hits, time0, time1, rng));
+            right = Box::new(BvhNode::new(rest, time0, time1, rng));
         }
 
         let box_left = left.bounding_box(time0, time1).unwrap();
@@ -89,9 +93,14 @@ impl BvhNode {
 
 impl Hit for BvhNode {
     fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        count_bvh_node();
+
         if self.aabb.hit(ray, t_min, t_max) {
             let hit_left = self.left.hit(ray, t_min, t_max);
-            let hit_right = self.right.hit(ray, t_min, t_max);
+
+            // Only look for hits in front of the closest one so far
+            let closest = hit_left.as_ref().map_or(t_max, |left| left.t);
+            let hit_right = self.right.hit(ray, t_min, closest);
 
             return match (hit_left, hit_right) {
                 (Some(left), Some(right)) => {
@@ -110,7 +119,7 @@ impl Hit for BvhNode {
         None
     }
 
-    fn bounding_box(&self, t0: f32, t1: f32) -> Option<Aabb> {
+    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<Aabb> {
         Some(self.aabb)
     }
 }
@@ -1,6 +1,6 @@
 use crate::ray::Ray;
+use crate::sampler::{in_unit_disk, Sampler};
 use crate::vector::Vector3;
-use crate::{random_float, random_in_unit_disk};
 use std::f32::consts::PI;
 
 #[derive(Debug, Default, Copy, Clone)]
@@ -9,7 +9,6 @@ pub struct Camera {
     lower_left_corner: Vector3,
     horizontal: Vector3,
     vertical: Vector3,
-    w: Vector3,
     u: Vector3,
     v: Vector3,
     lens_radius: f32,
@@ -18,6 +17,7 @@ pub struct Camera {
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         eye: Vector3,
         center: Vector3,
@@ -44,7 +44,6 @@ impl Camera {
                 - w * focus_dist,
             horizontal: u * half_width * focus_dist * 2.0,
             vertical: v * half_height * focus_dist * 2.0,
-            w,
             u,
             v,
             lens_radius: aperture / 2.0,
@@ -53,9 +52,9 @@ impl Camera {
         }
     }
 
-    pub fn ray(&self, s: f32, t: f32) -> Ray {
-        let rd = random_in_unit_disk() * self.lens_radius;
-        let time = self.time0 + (random_float() + self.time1 - self.time0);
+    pub fn ray(&self, s: f32, t: f32, sampler: &mut dyn Sampler) -> Ray {
+        let rd = in_unit_disk(sampler) * self.lens_radius;
+        let time = self.time0 + sampler.get_1d() * (self.time1 - self.time0);
         let offset = self.u * rd.x + self.v * rd.y;
 
         Ray::with_time(
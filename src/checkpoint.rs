@@ -0,0 +1,241 @@
+use crate::environment::MapFile;
+use crate::filter::{Filter, FilterKind};
+use crate::image::Framebuffer;
+use crate::mis::MisHeuristic;
+use crate::render::{Accumulator, Tile};
+use crate::sampler::SamplerKind;
+use crate::vector::Vector3;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"PTCK";
+const VERSION: u32 = 1;
+
+// Limits on the sizes a header may claim, checked before anything is allocated for them
+// so a corrupt file fails to read instead of running out of memory
+const MAX_STRING: usize = 1 << 16;
+// 8192x8192
+const MAX_PIXELS: usize = 1 << 26;
+
+// Everything needed to continue a progressive render
+// The random state of every pixel is derived from the seed and its sample count
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub scene: String,
+    pub seed: u64,
+    pub max_depth: usize,
+    pub min_bounces: usize,
+    pub filter: Filter,
+    // Times the camera shutter opens and closes
+    pub shutter: (f32, f32),
+    pub sampler: SamplerKind,
+    pub mis: MisHeuristic,
+    // Environment map replacing the scene's own
+    pub environment: Option<MapFile>,
+    // Region being rendered, the whole image if none
+    pub crop: Option<Tile>,
+    pub accumulator: Accumulator,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_string<W: Write>(out: &mut W, s: &str) -> io::Result<()> {
+    out.write_all(&(s.len() as u32).to_le_bytes())?;
+    out.write_all(s.as_bytes())
+}
+
+fn read_string<R: Read>(input: &mut R) -> io::Result<String> {
+    let len = read_u32(input)? as usize;
+    if len > MAX_STRING {
+        return Err(invalid("string is too long"));
+    }
+
+    let mut bytes = vec![0; len];
+    input.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|_| invalid("string is not UTF-8"))
+}
+
+fn read_u32<R: Read>(input: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(input: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f32<R: Read>(input: &mut R) -> io::Result<f32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(f32::from_le_bytes(bytes))
+}
+
+pub fn write_checkpoint<W: Write>(mut out: W, checkpoint: &Checkpoint) -> io::Result<()> {
+    let accumulator = &checkpoint.accumulator;
+
+    out.write_all(MAGIC)?;
+    out.write_all(&VERSION.to_le_bytes())?;
+    out.write_all(&(accumulator.width() as u32).to_le_bytes())?;
+    out.write_all(&(accumulator.height() as u32).to_le_bytes())?;
+    out.write_all(&checkpoint.seed.to_le_bytes())?;
+    out.write_all(&(checkpoint.max_depth as u32).to_le_bytes())?;
+    out.write_all(&(checkpoint.min_bounces as u32).to_le_bytes())?;
+    write_string(&mut out, &checkpoint.scene)?;
+    write_string(&mut out, checkpoint.filter.kind.name())?;
+    out.write_all(&checkpoint.filter.radius.to_le_bytes())?;
+    out.write_all(&checkpoint.shutter.0.to_le_bytes())?;
+    out.write_all(&checkpoint.shutter.1.to_le_bytes())?;
+    write_string(&mut out, checkpoint.sampler.name())?;
+    write_string(&mut out, checkpoint.mis.name())?;
+
+    // An empty path stands for the scene's environment
+    let environment = checkpoint.environment.clone().unwrap_or(MapFile {
+        path: String::new(),
+        rotation: 0.0,
+        intensity: 1.0,
+    });
+    write_string(&mut out, &environment.path)?;
+    out.write_all(&environment.rotation.to_le_bytes())?;
+    out.write_all(&environment.intensity.to_le_bytes())?;
+
+    // A flag, then the corners of the region
+    let crop = checkpoint.crop.map(|c| [c.x0, c.y0, c.x1, c.y1]);
+    out.write_all(&(crop.is_some() as u32).to_le_bytes())?;
+    for corner in crop.iter().flatten() {
+        out.write_all(&(*corner as u32).to_le_bytes())?;
+    }
+
+    let sums = accumulator.sum.pixels();
+    let weights = accumulator.weights.pixels();
+    let abs_weights = accumulator.abs_weights.pixels();
+    let luminance = accumulator.luminance.pixels();
+    let squares = accumulator.sum_squares.pixels();
+    let counts = accumulator.samples.pixels();
+    for i in 0..sums.len() {
+        out.write_all(&sums[i].x.to_le_bytes())?;
+        out.write_all(&sums[i].y.to_le_bytes())?;
+        out.write_all(&sums[i].z.to_le_bytes())?;
+        out.write_all(&weights[i].to_le_bytes())?;
+        out.write_all(&abs_weights[i].to_le_bytes())?;
+        out.write_all(&luminance[i].to_le_bytes())?;
+        out.write_all(&squares[i].to_le_bytes())?;
+        out.write_all(&counts[i].to_le_bytes())?;
+    }
+
+    out.flush()
+}
+
+pub fn read_checkpoint<R: Read>(mut input: R) -> io::Result<Checkpoint> {
+    let mut magic = [0; 4];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not a checkpoint file"));
+    }
+
+    if read_u32(&mut input)? != VERSION {
+        return Err(invalid("unsupported checkpoint version"));
+    }
+
+    let width = read_u32(&mut input)? as usize;
+    let height = read_u32(&mut input)? as usize;
+    let pixels = width
+        .checked_mul(height)
+        .filter(|&pixels| pixels <= MAX_PIXELS)
+        .ok_or_else(|| invalid("image is too large"))?;
+    let seed = read_u64(&mut input)?;
+    let max_depth = read_u32(&mut input)? as usize;
+    let min_bounces = read_u32(&mut input)? as usize;
+
+    let scene = read_string(&mut input)?;
+    let kind: FilterKind = read_string(&mut input)?
+        .parse()
+        .map_err(|_| invalid("unknown filter"))?;
+    let radius = read_f32(&mut input)?;
+    let shutter = (read_f32(&mut input)?, read_f32(&mut input)?);
+    let sampler = read_string(&mut input)?
+        .parse()
+        .map_err(|_| invalid("unknown sampler"))?;
+    let mis = read_string(&mut input)?
+        .parse()
+        .map_err(|_| invalid("unknown MIS heuristic"))?;
+    let environment = MapFile {
+        path: read_string(&mut input)?,
+        rotation: read_f32(&mut input)?,
+        intensity: read_f32(&mut input)?,
+    };
+    let environment = Some(environment).filter(|map| !map.path.is_empty());
+
+    let crop = match read_u32(&mut input)? {
+        0 => None,
+        1 => Some(Tile {
+            x0: read_u32(&mut input)? as usize,
+            y0: read_u32(&mut input)? as usize,
+            x1: read_u32(&mut input)? as usize,
+            y1: read_u32(&mut input)? as usize,
+        }),
+        _ => return Err(invalid("invalid crop flag")),
+    };
+    if let Some(crop) = crop {
+        if crop.x0 >= crop.x1 || crop.y0 >= crop.y1 || crop.x1 > width || crop.y1 > height {
+            return Err(invalid("crop region outside the image"));
+        }
+    }
+
+    let mut sums = Vec::with_capacity(pixels);
+    let mut weights = Vec::with_capacity(pixels);
+    let mut abs_weights = Vec::with_capacity(pixels);
+    let mut luminance = Vec::with_capacity(pixels);
+    let mut squares = Vec::with_capacity(pixels);
+    let mut counts = Vec::with_capacity(pixels);
+    for _ in 0..pixels {
+        let x = read_f32(&mut input)?;
+        let y = read_f32(&mut input)?;
+        let z = read_f32(&mut input)?;
+        sums.push(Vector3::new(x, y, z));
+        weights.push(read_f32(&mut input)?);
+        abs_weights.push(read_f32(&mut input)?);
+        luminance.push(read_f32(&mut input)?);
+        squares.push(read_f32(&mut input)?);
+        counts.push(read_u32(&mut input)?);
+    }
+
+    Ok(Checkpoint {
+        scene,
+        seed,
+        max_depth,
+        min_bounces,
+        filter: Filter { kind, radius },
+        shutter,
+        sampler,
+        mis,
+        environment,
+        crop,
+        accumulator: Accumulator {
+            sum: Framebuffer::from_pixels(width, height, sums),
+            weights: Framebuffer::from_pixels(width, height, weights),
+            abs_weights: Framebuffer::from_pixels(width, height, abs_weights),
+            luminance: Framebuffer::from_pixels(width, height, luminance),
+            sum_squares: Framebuffer::from_pixels(width, height, squares),
+            samples: Framebuffer::from_pixels(width, height, counts),
+        },
+    })
+}
+
+// Save a checkpoint, replacing the previous one only once the new file is complete
+pub fn save<P: AsRef<Path>>(path: P, checkpoint: &Checkpoint) -> io::Result<()> {
+    let path = path.as_ref();
+    let temp = path.with_extension("tmp");
+
+    write_checkpoint(BufWriter::new(File::create(&temp)?), checkpoint)?;
+    fs::rename(temp, path)
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Checkpoint> {
+    read_checkpoint(BufReader::new(File::open(path)?))
+}
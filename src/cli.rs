@@ -0,0 +1,862 @@
+use pathtracer::animation::Animation;
+use pathtracer::aov::Aov;
+use pathtracer::compare::PIXELS_PER_DEGREE;
+use pathtracer::denoise::DenoiseSettings;
+use pathtracer::environment::MapFile;
+use pathtracer::filter::Filter;
+use pathtracer::image::ImageFormat;
+use pathtracer::mis::MisHeuristic;
+use pathtracer::pipeline::{CompareOptions, Crop, DenoiseOptions, OutputOptions, RenderOptions};
+use pathtracer::render::{available_threads, RenderSettings, TileOrder};
+use pathtracer::sampler::SamplerKind;
+use pathtracer::scenes::{find_scene, SCENES};
+use pathtracer::tonemap::{Tonemap, Tonemapper};
+use std::convert::TryFrom;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+pub const USAGE: &str = "Usage: pathtracer [OPTIONS]
+       pathtracer denoise [OPTIONS] <INPUT>
+       pathtracer compare [OPTIONS] <IMAGE> <REFERENCE>
+
+Commands:
+  denoise                 Denoise a saved render, see 'pathtracer denoise --help'
+  compare                 Measure the error of a render, see 'pathtracer compare --help'
+
+Options:
+  -s, --scene <NAME>      Scene to render (default: colored_spheres)
+  -W, --width <PIXELS>    Image width (default: 500)
+  -H, --height <PIXELS>   Image height (default: 500)
+  -n, --samples <SPP>     Samples per pixel (default: 100)
+  -d, --max-depth <N>     Maximum number of bounces per path (default: 50)
+      --min-bounces <N>   Bounces before Russian roulette may end a path (default: 3)
+      --seed <N>          Seed for the random number generator (default: 0)
+  -t, --threads <N>       Number of worker threads (default: all cores)
+      --sampler <NAME>    Sample generator: independent, stratified, halton, sobol,
+                          bluenoise (default: independent)
+      --mis <NAME>        Weighting of light found both by sampling it and by
+                          scattering into it: balance, power (default: power)
+      --filter <NAME>     Pixel reconstruction filter: box, tent, gaussian, mitchell,
+                          lanczos (default: box)
+      --filter-radius <PIXELS>
+                          Filter radius (default: 0.5, 1, 1.5, 2 and 3 respectively)
+      --crop <X0,Y0,X1,Y1>
+                          Only render the pixels from X0,Y0 up to X1,Y1, the rest
+                          of the image stays black
+      --crop-window <X0,Y0,X1,Y1>
+                          Like --crop, as fractions of the image size from 0 to 1
+      --crop-output       Write only the cropped region instead of the full frame
+      --tile-order <NAME> Order tiles are rendered in: scanline, spiral, hilbert
+                          (default: scanline)
+      --environment <FILE>
+                          Light the scene with an equirectangular HDR or PFM image
+                          instead of its own background
+      --environment-rotation <DEGREES>
+                          Turn the environment map about the vertical axis (default: 0)
+      --environment-intensity <X>
+                          Scale the environment map's radiance (default: 1)
+  -o, --output <FILE>     Output image path (default: test.ppm)
+  -f, --format <FORMAT>   Output image format: ppm, png, pfm, hdr, exr
+                          (default: from the output extension)
+  -p, --pass-samples <SPP>
+                          Render progressively, adding SPP samples per pixel each pass
+      --checkpoint <FILE> Save progress to FILE while rendering progressively
+      --checkpoint-interval <SECONDS>
+                          Time between previews and checkpoints (default: 60)
+      --resume <FILE>     Continue the render saved in a checkpoint, restoring its
+                          scene, size, seed, max depth, min bounces,
+                          sampler, MIS weighting, filter, shutter, environment
+                          and crop
+  -a, --adaptive <ERROR>  Sample each pixel until the standard error of its sRGB encoded
+                          luminance is below ERROR, 1 being white, with --samples as
+                          the maximum
+      --min-samples <SPP> Samples per pixel before adaptive sampling starts (default: 16)
+      --heatmap <FILE>    Write an image of the per-pixel sample counts
+  -e, --exposure <STOPS>  Exposure adjustment before tonemapping (default: 0)
+      --tonemap <NAME>    Tonemapper: none, reinhard, reinhard-extended, aces, hable, agx
+                          (default: none)
+      --white <L>         White point luminance of reinhard-extended (default: 4)
+      --gamma <VALUE>     Display encoding: srgb or a gamma value (default: srgb)
+      --frames <FIRST>-<LAST>
+                          Render an animation, writing numbered images such as
+                          test_0001.ppm for every frame from FIRST to LAST
+      --fps <N>           Frames per second of the animation (default: 24)
+      --shutter-angle <DEGREES>
+                          Part of a frame the shutter is open for, 360 blurs motion
+                          over the whole frame (default: 180)
+      --report            Write render statistics as JSON next to the output image
+      --aov <NAME>[=<FILE>]
+                          Also write an AOV: normal, depth, albedo, position, id
+                          Without a file it becomes a layer of an EXR output, or is
+                          written next to the output, as in test.normal.ppm
+                          (may be repeated)
+      --denoise           Denoise the image, guided by the albedo, normal and depth AOVs
+  -l, --list-scenes       List the available scenes and exit
+  -h, --help              Print this message and exit";
+
+pub const DENOISE_USAGE: &str = "Usage: pathtracer denoise [OPTIONS] <INPUT>
+
+Denoise a PFM or EXR render. The albedo, normal and depth guides are taken from the
+layers of an EXR input written with --aov, or from separate files.
+
+Options:
+  -o, --output <FILE>     Output image path (default: <INPUT>.denoised.<EXT>)
+  -f, --format <FORMAT>   Output image format: ppm, png, pfm, hdr, exr
+                          (default: from the output extension)
+      --albedo <FILE>     Albedo guide image
+      --normal <FILE>     Normal guide image
+      --depth <FILE>      Depth guide image
+      --iterations <N>    Filter passes, each doubling the filter size (default: 5)
+      --sigma-color <S>   Tolerance to color differences (default: 0.6)
+  -t, --threads <N>       Number of worker threads (default: all cores)
+  -h, --help              Print this message and exit";
+
+pub const COMPARE_USAGE: &str = "Usage: pathtracer compare [OPTIONS] <IMAGE> <REFERENCE>
+
+Compare a render against a reference of the same size and print the MSE, RMSE, relMSE,
+PSNR, SSIM and FLIP error. PPM and PNG images are decoded from sRGB, PFM and EXR images
+are read as linear values.
+
+Options:
+  -o, --output <FILE>     False color FLIP error image (default: <IMAGE>.flip.png)
+  -f, --format <FORMAT>   Error image format: ppm, png, pfm, hdr, exr
+                          (default: from the output extension)
+      --ppd <PIXELS>      Pixels per degree of visual angle for FLIP (default: 67)
+      --json              Print the metrics as JSON
+  -h, --help              Print this message and exit";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CliError {
+    UnknownOption(String),
+    MissingValue(String),
+    InvalidValue {
+        option: String,
+        value: String,
+        expected: &'static str,
+    },
+    UnknownScene(String),
+    UnknownFormat(String),
+    UnknownExtension(String),
+    UnexpectedArgument(String),
+    MissingInput,
+    // Two options that cannot be used together
+    Conflict(&'static str, &'static str),
+    // An option that only applies together with another
+    Requires(&'static str, &'static str),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::UnknownOption(option) => write!(f, "unknown option '{}'", option),
+            CliError::MissingValue(option) => write!(f, "option '{}' requires a value", option),
+            CliError::InvalidValue {
+                option,
+                value,
+                expected,
+            } => write!(
+                f,
+                "invalid value '{}' for '{}': expected {}",
+                value, option, expected
+            ),
+            CliError::UnknownScene(name) => {
+                let names: Vec<&str> = SCENES.iter().map(|scene| scene.name).collect();
+                write!(
+                    f,
+                    "unknown scene '{}', available scenes: {}",
+                    name,
+                    names.join(", ")
+                )
+            }
+            CliError::UnknownFormat(name) => write!(
+                f,
+                "unknown output format '{}', available formats: {}",
+                name,
+                ImageFormat::NAMES.join(", ")
+            ),
+            CliError::UnknownExtension(path) => write!(
+                f,
+                "cannot tell the output format from '{}', use --format to pick one of: {}",
+                path,
+                ImageFormat::NAMES.join(", ")
+            ),
+            CliError::UnexpectedArgument(arg) => write!(f, "unexpected argument '{}'", arg),
+            CliError::MissingInput => write!(f, "no input image given"),
+            CliError::Conflict(a, b) => write!(f, "'{}' cannot be combined with '{}'", a, b),
+            CliError::Requires(a, b) => write!(f, "'{}' requires '{}'", a, b),
+        }
+    }
+}
+
+// A render as given on the command line
+pub struct Options {
+    pub settings: RenderSettings,
+    pub render: RenderOptions,
+    pub output: OutputOptions,
+    // Render every frame of the animation instead of a single image
+    pub sequence: Option<Animation>,
+}
+
+pub enum Command {
+    Render(Box<Options>),
+    Denoise(DenoiseOptions),
+    Compare {
+        options: CompareOptions,
+        // Print the metrics as JSON
+        json: bool,
+    },
+    ListScenes,
+    // Print the given usage text
+    Help(&'static str),
+}
+
+// Parse a positive integer option value
+fn positive<T>(option: &str, value: String) -> Result<T, CliError>
+where
+    T: FromStr + PartialOrd + Default,
+{
+    match value.parse::<T>() {
+        Ok(n) if n > T::default() => Ok(n),
+        _ => Err(CliError::InvalidValue {
+            option: option.to_string(),
+            value,
+            expected: "a positive integer",
+        }),
+    }
+}
+
+// Parse four comma separated numbers
+fn four<T: FromStr>(
+    option: &str,
+    value: String,
+    expected: &'static str,
+) -> Result<[T; 4], CliError> {
+    let numbers: Vec<T> = value
+        .split(',')
+        .map(|n| n.trim().parse())
+        .collect::<Result<_, _>>()
+        .unwrap_or_default();
+
+    <[T; 4]>::try_from(numbers).map_err(|_| CliError::InvalidValue {
+        option: option.to_string(),
+        value,
+        expected,
+    })
+}
+
+// Guess an output format from the file extension
+fn format_of(path: String) -> Result<ImageFormat, CliError> {
+    ImageFormat::from_path(&path).ok_or(CliError::UnknownExtension(path))
+}
+
+// Split "--option=value" into the option and its inline value
+fn split_option(arg: String) -> (String, Option<String>) {
+    match arg.find('=') {
+        Some(i) if arg.starts_with("--") => (arg[..i].to_string(), Some(arg[i + 1..].to_string())),
+        _ => (arg, None),
+    }
+}
+
+// Parse the arguments following the program name
+pub fn parse_args<I>(args: I) -> Result<Command, CliError>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut args = args.into_iter().peekable();
+    if args.peek().map(String::as_str) == Some("denoise") {
+        args.next();
+        return parse_denoise_args(args);
+    }
+    if args.peek().map(String::as_str) == Some("compare") {
+        args.next();
+        return parse_compare_args(args);
+    }
+
+    let mut scene = "colored_spheres".to_string();
+    let mut width = 500;
+    let mut height = 500;
+    let mut samples = 100;
+    let mut max_depth = 50;
+    let mut min_bounces = 3;
+    let mut seed = 0;
+    let mut threads = available_threads();
+    let mut sampler = SamplerKind::Independent;
+    let mut mis = MisHeuristic::Power;
+    let mut filter = Filter::default();
+    let mut filter_radius = None;
+    let mut crop = None;
+    let mut crop_output = false;
+    let mut tile_order = TileOrder::Scanline;
+    let mut environment = None;
+    let mut environment_rotation = None;
+    let mut environment_intensity = None;
+    let mut output = "test.ppm".to_string();
+    let mut format = None;
+    let mut pass_samples = None;
+    let mut checkpoint = None;
+    let mut checkpoint_interval = 60;
+    let mut resume = None;
+    let mut adaptive = None;
+    let mut min_samples = 16;
+    let mut heatmap = None;
+    let mut tonemap = Tonemap::default();
+    let mut white = None;
+    let mut report = false;
+    let mut aovs = Vec::new();
+    let mut denoise = false;
+    let mut animation = Animation::default();
+    let mut sequence = false;
+
+    while let Some(arg) = args.next() {
+        // Accept both "--option value" and "--option=value"
+        let (option, inline) = split_option(arg);
+
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| CliError::MissingValue(option.clone()))
+        };
+
+        match option.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(USAGE)),
+            "-l" | "--list-scenes" => return Ok(Command::ListScenes),
+            "-s" | "--scene" => scene = value()?,
+            "-W" | "--width" => width = positive(&option, value()?)?,
+            "-H" | "--height" => height = positive(&option, value()?)?,
+            "-n" | "--samples" => samples = positive(&option, value()?)?,
+            "-d" | "--max-depth" => max_depth = positive(&option, value()?)?,
+            "-t" | "--threads" => threads = positive(&option, value()?)?,
+            "-o" | "--output" => output = value()?,
+            "--sampler" => {
+                let value = value()?;
+                sampler = value.parse().map_err(|_| CliError::InvalidValue {
+                    option: option.clone(),
+                    value,
+                    expected: "one of independent, stratified, halton, sobol, bluenoise",
+                })?
+            }
+            "--mis" => {
+                let value = value()?;
+                mis = value.parse().map_err(|_| CliError::InvalidValue {
+                    option: option.clone(),
+                    value,
+                    expected: "one of balance, power",
+                })?
+            }
+            "--filter" => {
+                let value = value()?;
+                let kind = value.parse().map_err(|_| CliError::InvalidValue {
+                    option: option.clone(),
+                    value,
+                    expected: "one of box, tent, gaussian, mitchell, lanczos",
+                })?;
+                filter = Filter::new(kind);
+            }
+            "--filter-radius" => {
+                let value = value()?;
+                filter_radius = match value.parse::<f32>() {
+                    Ok(radius) if radius > 0.0 => Some(radius),
+                    _ => {
+                        return Err(CliError::InvalidValue {
+                            option,
+                            value,
+                            expected: "a positive number of pixels",
+                        })
+                    }
+                }
+            }
+            "--crop" => {
+                if let Some(Crop::Window(_)) = crop {
+                    return Err(CliError::Conflict("--crop", "--crop-window"));
+                }
+                let value = value()?;
+                crop = Some(Crop::Pixels(four(
+                    &option,
+                    value,
+                    "four pixel coordinates X0,Y0,X1,Y1",
+                )?))
+            }
+            "--crop-window" => {
+                if let Some(Crop::Pixels(_)) = crop {
+                    return Err(CliError::Conflict("--crop-window", "--crop"));
+                }
+                let value = value()?;
+                let window: [f32; 4] = four(&option, value.clone(), "four fractions X0,Y0,X1,Y1")?;
+                if window.iter().any(|t| !(0.0..=1.0).contains(t)) {
+                    return Err(CliError::InvalidValue {
+                        option,
+                        value,
+                        expected: "four fractions X0,Y0,X1,Y1 between 0 and 1",
+                    });
+                }
+                crop = Some(Crop::Window(window))
+            }
+            "--crop-output" => crop_output = true,
+            "--tile-order" => {
+                let value = value()?;
+                tile_order = value.parse().map_err(|_| CliError::InvalidValue {
+                    option: option.clone(),
+                    value,
+                    expected: "one of scanline, spiral, hilbert",
+                })?
+            }
+            "-p" | "--pass-samples" => pass_samples = Some(positive(&option, value()?)?),
+            "--checkpoint" => checkpoint = Some(value()?),
+            "--checkpoint-interval" => {
+                let value = value()?;
+                checkpoint_interval = value.parse().map_err(|_| CliError::InvalidValue {
+                    option: option.clone(),
+                    value,
+                    expected: "a number of seconds",
+                })?
+            }
+            "--resume" => resume = Some(value()?),
+            "-a" | "--adaptive" => {
+                let value = value()?;
+                adaptive = match value.parse::<f32>() {
+                    Ok(error) if error > 0.0 => Some(error),
+                    _ => {
+                        return Err(CliError::InvalidValue {
+                            option,
+                            value,
+                            expected: "a positive number",
+                        })
+                    }
+                }
+            }
+            "--min-samples" => min_samples = positive(&option, value()?)?,
+            "--heatmap" => heatmap = Some(value()?),
+            "--report" => report = true,
+            "--denoise" => denoise = true,
+            "--aov" => {
+                let value = value()?;
+                let (name, path) = match value.find('=') {
+                    Some(i) => (&value[..i], Some(value[i + 1..].to_string())),
+                    None => (value.as_str(), None),
+                };
+
+                let aov = name.parse::<Aov>().map_err(|_| CliError::InvalidValue {
+                    option: option.clone(),
+                    value: value.clone(),
+                    expected: "one of normal, depth, albedo, position, id",
+                })?;
+                aovs.push((aov, path));
+            }
+            "--frames" => {
+                let value = value()?;
+                let frames = match value.find('-') {
+                    Some(i) => value[..i]
+                        .parse()
+                        .and_then(|first| value[i + 1..].parse().map(|last| (first, last))),
+                    None => value.parse().map(|frame| (frame, frame)),
+                };
+
+                match frames {
+                    Ok((first, last)) if first <= last => {
+                        animation.first_frame = first;
+                        animation.last_frame = last;
+                        sequence = true;
+                    }
+                    _ => {
+                        return Err(CliError::InvalidValue {
+                            option,
+                            value,
+                            expected: "a frame range FIRST-LAST or a single frame",
+                        })
+                    }
+                }
+            }
+            "--fps" => {
+                let value = value()?;
+                animation.fps = match value.parse::<f32>() {
+                    Ok(fps) if fps > 0.0 => fps,
+                    _ => {
+                        return Err(CliError::InvalidValue {
+                            option,
+                            value,
+                            expected: "a positive number",
+                        })
+                    }
+                }
+            }
+            "--shutter-angle" => {
+                let value = value()?;
+                animation.shutter_angle = match value.parse::<f32>() {
+                    Ok(angle) if (0.0..=360.0).contains(&angle) => angle,
+                    _ => {
+                        return Err(CliError::InvalidValue {
+                            option,
+                            value,
+                            expected: "an angle from 0 to 360 degrees",
+                        })
+                    }
+                }
+            }
+            "--environment" => environment = Some(value()?),
+            "--environment-rotation" => {
+                let value = value()?;
+                environment_rotation = Some(value.parse().map_err(|_| CliError::InvalidValue {
+                    option: option.clone(),
+                    value,
+                    expected: "an angle in degrees",
+                })?)
+            }
+            "--environment-intensity" => {
+                let value = value()?;
+                environment_intensity = match value.parse::<f32>() {
+                    Ok(intensity) if intensity >= 0.0 => Some(intensity),
+                    _ => {
+                        return Err(CliError::InvalidValue {
+                            option,
+                            value,
+                            expected: "a non-negative number",
+                        })
+                    }
+                }
+            }
+            "-e" | "--exposure" => {
+                let value = value()?;
+                tonemap.exposure = value.parse().map_err(|_| CliError::InvalidValue {
+                    option: option.clone(),
+                    value,
+                    expected: "a number of stops",
+                })?
+            }
+            "--tonemap" => {
+                let value = value()?;
+                tonemap.tonemapper = value.parse().map_err(|_| CliError::InvalidValue {
+                    option: option.clone(),
+                    value,
+                    expected: "one of none, reinhard, reinhard-extended, aces, hable, agx",
+                })?
+            }
+            "--white" => {
+                let value = value()?;
+                white = match value.parse::<f32>() {
+                    Ok(white) if white > 0.0 => Some(white),
+                    _ => {
+                        return Err(CliError::InvalidValue {
+                            option,
+                            value,
+                            expected: "a positive number",
+                        })
+                    }
+                }
+            }
+            "--gamma" => {
+                let value = value()?;
+                tonemap.transfer = value.parse().map_err(|_| CliError::InvalidValue {
+                    option: option.clone(),
+                    value,
+                    expected: "srgb or a positive number",
+                })?
+            }
+            "--min-bounces" => {
+                let value = value()?;
+                min_bounces = value.parse().map_err(|_| CliError::InvalidValue {
+                    option: option.clone(),
+                    value,
+                    expected: "a non-negative integer",
+                })?
+            }
+            "--seed" => {
+                let value = value()?;
+                seed = value.parse().map_err(|_| CliError::InvalidValue {
+                    option: option.clone(),
+                    value,
+                    expected: "a non-negative integer",
+                })?
+            }
+            "-f" | "--format" => {
+                let value = value()?;
+                format = Some(value.parse().map_err(|_| CliError::UnknownFormat(value))?)
+            }
+            _ => return Err(CliError::UnknownOption(option)),
+        }
+    }
+
+    let scene = find_scene(&scene).ok_or(CliError::UnknownScene(scene))?;
+
+    // A checkpoint holds a single image
+    if sequence && resume.is_some() {
+        return Err(CliError::Conflict("--frames", "--resume"));
+    }
+    if sequence && checkpoint.is_some() {
+        return Err(CliError::Conflict("--frames", "--checkpoint"));
+    }
+
+    // An explicit format wins over the output extension
+    let format = match format {
+        Some(format) => format,
+        None => format_of(output.clone())?,
+    };
+
+    // The white point can be given before or after the tonemapper
+    // Only one tonemapper has a white point
+    match (white, tonemap.tonemapper) {
+        (Some(white), Tonemapper::ExtendedReinhard { .. }) => {
+            tonemap.tonemapper = Tonemapper::ExtendedReinhard { white };
+        }
+        (Some(_), _) => return Err(CliError::Requires("--white", "--tonemap reinhard-extended")),
+        (None, _) => {}
+    }
+
+    // The radius can be given before or after the filter
+    if let Some(radius) = filter_radius {
+        filter.radius = radius;
+    }
+
+    let environment = match environment {
+        Some(path) => Some(MapFile {
+            path,
+            rotation: environment_rotation.unwrap_or(0.0),
+            intensity: environment_intensity.unwrap_or(1.0),
+        }),
+        None if environment_rotation.is_some() => {
+            return Err(CliError::Requires(
+                "--environment-rotation",
+                "--environment",
+            ))
+        }
+        None if environment_intensity.is_some() => {
+            return Err(CliError::Requires(
+                "--environment-intensity",
+                "--environment",
+            ))
+        }
+        None => None,
+    };
+
+    let heatmap = match heatmap {
+        Some(path) => Some((path.clone(), format_of(path)?)),
+        None => None,
+    };
+
+    // AOVs without a file go into the EXR output, or next to any other output
+    let mut aov_files = Vec::new();
+    let mut aov_layers = Vec::new();
+    for (aov, path) in aovs {
+        match path {
+            Some(path) => aov_files.push((aov, path.clone(), format_of(path)?)),
+            None if format == ImageFormat::Exr => aov_layers.push(aov),
+            None => {
+                let extension = format!("{}.{}", aov.name(), format.extension());
+                let path = Path::new(&output).with_extension(extension);
+                aov_files.push((aov, path.to_string_lossy().into_owned(), format));
+            }
+        }
+    }
+
+    let settings = RenderSettings {
+        max_depth,
+        min_bounces,
+        seed,
+        threads,
+        sampler,
+        mis,
+        filter,
+        tile_order,
+        ..RenderSettings::new(width, height, samples)
+    };
+
+    let render = RenderOptions {
+        scene,
+        crop,
+        shutter: animation.shutter(animation.first_frame),
+        environment,
+        pass_samples,
+        adaptive,
+        min_samples,
+        checkpoint,
+        checkpoint_interval: Duration::from_secs(checkpoint_interval),
+        resume,
+    };
+
+    let output = OutputOptions {
+        path: output,
+        format,
+        tonemap,
+        crop_output,
+        aovs: aov_files,
+        aov_layers,
+        denoise,
+        heatmap,
+        report,
+    };
+
+    Ok(Command::Render(Box::new(Options {
+        settings,
+        render,
+        output,
+        sequence: Some(animation).filter(|_| sequence),
+    })))
+}
+
+// Parse the arguments following "denoise"
+fn parse_denoise_args<I>(mut args: I) -> Result<Command, CliError>
+where
+    I: Iterator<Item = String>,
+{
+    let mut input = None;
+    let mut albedo = None;
+    let mut normal = None;
+    let mut depth = None;
+    let mut output = None;
+    let mut format = None;
+    let mut settings = DenoiseSettings {
+        threads: available_threads(),
+        ..DenoiseSettings::default()
+    };
+
+    while let Some(arg) = args.next() {
+        let (option, inline) = split_option(arg);
+
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| CliError::MissingValue(option.clone()))
+        };
+
+        match option.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(DENOISE_USAGE)),
+            "-o" | "--output" => output = Some(value()?),
+            "--albedo" => albedo = Some(value()?),
+            "--normal" => normal = Some(value()?),
+            "--depth" => depth = Some(value()?),
+            "--iterations" => settings.iterations = positive(&option, value()?)?,
+            "-t" | "--threads" => settings.threads = positive(&option, value()?)?,
+            "--sigma-color" => {
+                let value = value()?;
+                settings.sigma_color = match value.parse::<f32>() {
+                    Ok(sigma) if sigma > 0.0 => sigma,
+                    _ => {
+                        return Err(CliError::InvalidValue {
+                            option,
+                            value,
+                            expected: "a positive number",
+                        })
+                    }
+                }
+            }
+            "-f" | "--format" => {
+                let value = value()?;
+                format = Some(value.parse().map_err(|_| CliError::UnknownFormat(value))?)
+            }
+            _ if option.starts_with('-') => return Err(CliError::UnknownOption(option)),
+            _ if input.is_none() => input = Some(option),
+            _ => return Err(CliError::UnexpectedArgument(option)),
+        }
+    }
+
+    let input = input.ok_or(CliError::MissingInput)?;
+
+    // By default the output goes next to the input, in the same format
+    let output = match output {
+        Some(output) => output,
+        None => {
+            let format = format_of(input.clone())?;
+            let extension = format!("denoised.{}", format.extension());
+            Path::new(&input)
+                .with_extension(extension)
+                .to_string_lossy()
+                .into_owned()
+        }
+    };
+
+    let format = match format {
+        Some(format) => format,
+        None => format_of(output.clone())?,
+    };
+
+    Ok(Command::Denoise(DenoiseOptions {
+        input,
+        albedo,
+        normal,
+        depth,
+        output,
+        format,
+        settings,
+    }))
+}
+
+// Parse the arguments following "compare"
+fn parse_compare_args<I>(mut args: I) -> Result<Command, CliError>
+where
+    I: Iterator<Item = String>,
+{
+    let mut inputs = Vec::new();
+    let mut output = None;
+    let mut format = None;
+    let mut pixels_per_degree = PIXELS_PER_DEGREE;
+    let mut json = false;
+
+    while let Some(arg) = args.next() {
+        let (option, inline) = split_option(arg);
+
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| CliError::MissingValue(option.clone()))
+        };
+
+        match option.as_str() {
+            "-h" | "--help" => return Ok(Command::Help(COMPARE_USAGE)),
+            "-o" | "--output" => output = Some(value()?),
+            "--json" => json = true,
+            "--ppd" => {
+                let value = value()?;
+                pixels_per_degree = match value.parse::<f32>() {
+                    Ok(ppd) if ppd > 0.0 => ppd,
+                    _ => {
+                        return Err(CliError::InvalidValue {
+                            option,
+                            value,
+                            expected: "a positive number",
+                        })
+                    }
+                }
+            }
+            "-f" | "--format" => {
+                let value = value()?;
+                format = Some(value.parse().map_err(|_| CliError::UnknownFormat(value))?)
+            }
+            _ if option.starts_with('-') => return Err(CliError::UnknownOption(option)),
+            _ if inputs.len() < 2 => inputs.push(option),
+            _ => return Err(CliError::UnexpectedArgument(option)),
+        }
+    }
+
+    if inputs.len() < 2 {
+        return Err(CliError::MissingInput);
+    }
+    let reference = inputs.pop().unwrap();
+    let image = inputs.pop().unwrap();
+
+    let output = output.unwrap_or_else(|| {
+        Path::new(&image)
+            .with_extension("flip.png")
+            .to_string_lossy()
+            .into_owned()
+    });
+
+    let format = match format {
+        Some(format) => format,
+        None => format_of(output.clone())?,
+    };
+
+    Ok(Command::Compare {
+        options: CompareOptions {
+            image,
+            reference,
+            output,
+            format,
+            pixels_per_degree,
+        },
+        json,
+    })
+}
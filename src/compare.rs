@@ -0,0 +1,457 @@
+use crate::image::{luminance, Framebuffer};
+use crate::tonemap::Transfer;
+use crate::vector::Vector3;
+use std::f32::consts::PI;
+
+// Viewing a 0.7 m wide 4K monitor from 0.7 m, the FLIP default
+pub const PIXELS_PER_DEGREE: f32 = 67.0;
+
+// Keeps the relative error finite where the reference is black
+const REL_MSE_EPSILON: f32 = 0.01;
+
+// How far an image is from a reference
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    // Mean squared error over every channel of the linear values
+    pub mse: f32,
+    pub rmse: f32,
+    // Squared error relative to the squared reference
+    pub rel_mse: f32,
+    // Peak signal to noise ratio in dB, for a peak of 1
+    pub psnr: f32,
+    // Mean structural similarity of the display luminance, 1 for identical images
+    pub ssim: f32,
+    // Mean perceived difference when flipping between the images, from 0 to 1
+    pub flip: f32,
+    // Perceived difference of every pixel
+    pub flip_map: Framebuffer<f32>,
+}
+
+impl Comparison {
+    pub fn summary(&self) -> String {
+        let rows = [
+            ("MSE", format!("{:.6e}", self.mse)),
+            ("RMSE", format!("{:.6e}", self.rmse)),
+            ("relMSE", format!("{:.6e}", self.rel_mse)),
+            ("PSNR", format!("{:.3} dB", self.psnr)),
+            ("SSIM", format!("{:.6}", self.ssim)),
+            ("FLIP", format!("{:.6}", self.flip)),
+        ];
+
+        let lines: Vec<String> = rows
+            .iter()
+            .map(|(name, value)| format!("{:<8}{}", format!("{}:", name), value))
+            .collect();
+
+        lines.join("\n")
+    }
+
+    // Machine readable metrics, one flat JSON object
+    pub fn to_json(&self) -> String {
+        let fields = [
+            ("mse", self.mse),
+            ("rmse", self.rmse),
+            ("rel_mse", self.rel_mse),
+            ("psnr", self.psnr),
+            ("ssim", self.ssim),
+            ("flip", self.flip),
+        ];
+
+        // JSON has no infinity, identical images get a null PSNR
+        let lines: Vec<String> = fields
+            .iter()
+            .map(|(name, value)| match value.is_finite() {
+                true => format!("  \"{}\": {}", name, value),
+                false => format!("  \"{}\": null", name),
+            })
+            .collect();
+
+        format!("{{\n{}\n}}\n", lines.join(",\n"))
+    }
+}
+
+// Compare an image against a reference of the same size, both linear
+pub fn compare(
+    image: &Framebuffer<Vector3>,
+    reference: &Framebuffer<Vector3>,
+    pixels_per_degree: f32,
+) -> Comparison {
+    assert_eq!(
+        (image.width(), image.height()),
+        (reference.width(), reference.height()),
+        "compared images differ in size"
+    );
+
+    let channels = (image.pixels().len() * 3).max(1) as f64;
+    let mut squared = 0.0;
+    let mut relative = 0.0;
+
+    for (a, b) in image.pixels().iter().zip(reference.pixels()) {
+        for i in 0..3 {
+            let d = (a[i] - b[i]) as f64;
+            squared += d * d;
+            relative += d * d / (b[i] * b[i] + REL_MSE_EPSILON) as f64;
+        }
+    }
+
+    let mse = (squared / channels) as f32;
+    let flip_map = flip(image, reference, pixels_per_degree);
+    let flip = mean(&flip_map);
+
+    Comparison {
+        mse,
+        rmse: mse.sqrt(),
+        rel_mse: (relative / channels) as f32,
+        psnr: -10.0 * mse.log10(),
+        ssim: ssim(image, reference),
+        flip,
+        flip_map,
+    }
+}
+
+// Clamp every channel to [0, 1]
+fn clamp(c: Vector3) -> Vector3 {
+    Vector3::new(
+        c.x.clamp(0.0, 1.0),
+        c.y.clamp(0.0, 1.0),
+        c.z.clamp(0.0, 1.0),
+    )
+}
+
+fn mean(image: &Framebuffer<f32>) -> f32 {
+    let sum: f64 = image.pixels().iter().map(|&v| v as f64).sum();
+    (sum / image.pixels().len().max(1) as f64) as f32
+}
+
+// Convolve with a separable kernel, one odd length kernel per axis, repeating the
+// edge pixels
+fn convolve(image: &Framebuffer<f32>, kx: &[f32], ky: &[f32]) -> Framebuffer<f32> {
+    let (width, height) = (image.width(), image.height());
+    let clamp = |i: isize, size: usize| i.clamp(0, size as isize - 1) as usize;
+
+    let mut rows: Framebuffer<f32> = Framebuffer::new(width, height);
+    let rx = (kx.len() / 2) as isize;
+    for y in 0..height {
+        for x in 0..width {
+            let sum = kx
+                .iter()
+                .enumerate()
+                .map(|(i, k)| k * image.get(clamp(x as isize + i as isize - rx, width), y));
+            rows.set(x, y, sum.sum());
+        }
+    }
+
+    let mut result = Framebuffer::new(width, height);
+    let ry = (ky.len() / 2) as isize;
+    for y in 0..height {
+        for x in 0..width {
+            let sum = ky
+                .iter()
+                .enumerate()
+                .map(|(i, k)| k * rows.get(x, clamp(y as isize + i as isize - ry, height)));
+            result.set(x, y, sum.sum());
+        }
+    }
+
+    result
+}
+
+// Samples of a function over three standard deviations either side of zero
+fn kernel<F: Fn(f32) -> f32>(sigma: f32, f: F) -> Vec<f32> {
+    let radius = (3.0 * sigma).ceil().max(1.0) as isize;
+    (-radius..=radius).map(|x| f(x as f32)).collect()
+}
+
+// Normalized Gaussian
+fn gaussian(sigma: f32) -> Vec<f32> {
+    let k = kernel(sigma, |x| (-x * x / (2.0 * sigma * sigma)).exp());
+    let sum: f32 = k.iter().sum();
+    k.iter().map(|v| v / sum).collect()
+}
+
+// Scale the positive and negative weights of a kernel to sum to 1 and -1
+fn balance(k: Vec<f32>) -> Vec<f32> {
+    let positive: f32 = k.iter().filter(|&&v| v > 0.0).sum();
+    let negative: f32 = -k.iter().filter(|&&v| v < 0.0).sum::<f32>();
+    k.iter()
+        .map(|&v| if v > 0.0 { v / positive } else { v / negative })
+        .collect()
+}
+
+// Structural similarity of the display luminance, over 11x11 Gaussian windows
+fn ssim(image: &Framebuffer<Vector3>, reference: &Framebuffer<Vector3>) -> f32 {
+    const C1: f32 = 0.01 * 0.01;
+    const C2: f32 = 0.03 * 0.03;
+
+    let display = |image: &Framebuffer<Vector3>| {
+        image.map(|p| {
+            let p = clamp(p);
+            let srgb = Transfer::Srgb;
+            luminance(Vector3::new(
+                srgb.encode(p.x),
+                srgb.encode(p.y),
+                srgb.encode(p.z),
+            ))
+        })
+    };
+
+    let (a, b) = (display(image), display(reference));
+    let window = gaussian(1.5);
+    let blur = |image: &Framebuffer<f32>| convolve(image, &window, &window);
+    let product = |a: &Framebuffer<f32>, b: &Framebuffer<f32>| {
+        let pixels = a.pixels().iter().zip(b.pixels()).map(|(x, y)| x * y);
+        Framebuffer::from_pixels(a.width(), a.height(), pixels.collect())
+    };
+
+    let (mean_a, mean_b) = (blur(&a), blur(&b));
+    let (square_a, square_b, ab) = (
+        blur(&product(&a, &a)),
+        blur(&product(&b, &b)),
+        blur(&product(&a, &b)),
+    );
+
+    let map = (0..a.pixels().len()).map(|i| {
+        let (ma, mb) = (mean_a.pixels()[i], mean_b.pixels()[i]);
+        let variance_a = square_a.pixels()[i] - ma * ma;
+        let variance_b = square_b.pixels()[i] - mb * mb;
+        let covariance = ab.pixels()[i] - ma * mb;
+
+        ((2.0 * ma * mb + C1) * (2.0 * covariance + C2))
+            / ((ma * ma + mb * mb + C1) * (variance_a + variance_b + C2))
+    });
+
+    mean(&Framebuffer::from_pixels(
+        a.width(),
+        a.height(),
+        map.collect(),
+    ))
+}
+
+// D65 white in CIE XYZ
+const WHITE: Vector3 = Vector3 {
+    x: 0.950_428_5,
+    y: 1.0,
+    z: 1.088_900_4,
+};
+
+fn rgb_to_xyz(c: Vector3) -> Vector3 {
+    Vector3::new(
+        0.412_456_4 * c.x + 0.357_576_1 * c.y + 0.180_437_5 * c.z,
+        0.212_672_9 * c.x + 0.715_152_2 * c.y + 0.072_175 * c.z,
+        0.019_333_9 * c.x + 0.119_192 * c.y + 0.950_304_1 * c.z,
+    )
+}
+
+fn xyz_to_rgb(c: Vector3) -> Vector3 {
+    Vector3::new(
+        3.240_454_2 * c.x - 1.537_138_5 * c.y - 0.498_531_4 * c.z,
+        -0.969_266 * c.x + 1.876_010_8 * c.y + 0.041_556 * c.z,
+        0.055_643_4 * c.x - 0.204_025_9 * c.y + 1.057_225_2 * c.z,
+    )
+}
+
+// Linearized CIELAB, opponent channels in which the eye's contrast sensitivity can be
+// applied by linear filtering
+fn xyz_to_ycxcz(c: Vector3) -> Vector3 {
+    let c = c / WHITE;
+    Vector3::new(116.0 * c.y - 16.0, 500.0 * (c.x - c.y), 200.0 * (c.y - c.z))
+}
+
+fn ycxcz_to_xyz(c: Vector3) -> Vector3 {
+    let y = (c.x + 16.0) / 116.0;
+    Vector3::new(y + c.y / 500.0, y, y - c.z / 200.0) * WHITE
+}
+
+// CIELAB with the Hunt effect, colorfulness growing with lightness
+fn hunt_lab(c: Vector3) -> Vector3 {
+    let f = |t: f32| {
+        const DELTA: f32 = 6.0 / 29.0;
+        if t > DELTA * DELTA * DELTA {
+            t.cbrt()
+        } else {
+            t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+        }
+    };
+
+    let c = rgb_to_xyz(c) / WHITE;
+    let (fx, fy, fz) = (f(c.x), f(c.y), f(c.z));
+    let l = 116.0 * fy - 16.0;
+
+    Vector3::new(
+        l,
+        0.01 * l * 500.0 * (fx - fy),
+        0.01 * l * 200.0 * (fy - fz),
+    )
+}
+
+// Lightness difference plus chroma distance, suited to large color differences
+fn hyab(a: Vector3, b: Vector3) -> f32 {
+    (a.x - b.x).abs() + ((a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt()
+}
+
+// Blur the opponent channels by the contrast sensitivity of the eye, each a sum of up
+// to two Gaussians given by weight and spread in degrees squared
+fn contrast_sensitivity(
+    image: &Framebuffer<Vector3>,
+    pixels_per_degree: f32,
+) -> Framebuffer<Vector3> {
+    const CHANNELS: [[(f32, f32); 2]; 3] = [
+        [(1.0, 0.0047), (0.0, 1e-5)],
+        [(1.0, 0.0053), (0.0, 1e-5)],
+        [(34.1, 0.04), (13.5, 0.025)],
+    ];
+
+    let mut filtered: Framebuffer<Vector3> = Framebuffer::new(image.width(), image.height());
+    for (channel, gaussians) in CHANNELS.iter().enumerate() {
+        let values = image.map(|p| p[channel]);
+        let mut sum = Framebuffer::new(image.width(), image.height());
+        let mut total = 0.0;
+
+        for &(a, b) in gaussians.iter().filter(|(a, _)| *a > 0.0) {
+            // A Gaussian's share of the kernel is proportional to its integral
+            let weight = a * b.sqrt();
+            let sigma = (b / (2.0 * PI * PI)).sqrt() * pixels_per_degree;
+            let k = gaussian(sigma);
+            let blurred = convolve(&values, &k, &k);
+
+            for (s, v) in sum.pixels_mut().iter_mut().zip(blurred.pixels()) {
+                *s += weight * v;
+            }
+            total += weight;
+        }
+
+        for (p, s) in filtered.pixels_mut().iter_mut().zip(sum.pixels()) {
+            p[channel] = s / total;
+        }
+    }
+
+    filtered
+}
+
+// Edge and point strength of the achromatic channel, at the scale of the eye's
+// sensitivity to features
+fn features(
+    lightness: &Framebuffer<f32>,
+    pixels_per_degree: f32,
+) -> (Framebuffer<f32>, Framebuffer<f32>) {
+    let sigma = 0.5 * 0.082 * pixels_per_degree;
+    let smooth = gaussian(sigma);
+    let g = |x: f32| (-x * x / (2.0 * sigma * sigma)).exp();
+    let edge = balance(kernel(sigma, |x| -x * g(x)));
+    let point = balance(kernel(sigma, |x| (x * x / (sigma * sigma) - 1.0) * g(x)));
+
+    let magnitude = |k: &[f32]| {
+        let dx = convolve(lightness, k, &smooth);
+        let dy = convolve(lightness, &smooth, k);
+        let pixels = dx
+            .pixels()
+            .iter()
+            .zip(dy.pixels())
+            .map(|(x, y)| x.hypot(*y));
+        Framebuffer::from_pixels(lightness.width(), lightness.height(), pixels.collect())
+    };
+
+    (magnitude(&edge), magnitude(&point))
+}
+
+// Per-pixel error in the manner of LDR-FLIP (Andersson et al. 2020): a color difference
+// of the images as the eye resolves them, raised by differences in edges and points
+// Values are clamped to the displayable range first
+pub fn flip(
+    image: &Framebuffer<Vector3>,
+    reference: &Framebuffer<Vector3>,
+    pixels_per_degree: f32,
+) -> Framebuffer<f32> {
+    const QC: f32 = 0.7;
+    const PC: f32 = 0.4;
+    const PT: f32 = 0.95;
+    const QF: f32 = 0.5;
+
+    let opponent = |image: &Framebuffer<Vector3>| image.map(|p| xyz_to_ycxcz(rgb_to_xyz(clamp(p))));
+    let (a, b) = (opponent(image), opponent(reference));
+
+    // Color difference of the filtered images, compressed so most of the range goes to
+    // small differences
+    let perceived = |ycxcz: &Framebuffer<Vector3>| {
+        contrast_sensitivity(ycxcz, pixels_per_degree)
+            .map(|p| hunt_lab(clamp(xyz_to_rgb(ycxcz_to_xyz(p)))))
+    };
+    let (color_a, color_b) = (perceived(&a), perceived(&b));
+
+    let green = hunt_lab(Vector3::new(0.0, 1.0, 0.0));
+    let blue = hunt_lab(Vector3::new(0.0, 0.0, 1.0));
+    let cmax = hyab(green, blue).powf(QC);
+
+    // Lightness in [0, 1]
+    let lightness = |ycxcz: &Framebuffer<Vector3>| ycxcz.map(|p| (p.x + 16.0) / 116.0);
+    let (edges_a, points_a) = features(&lightness(&a), pixels_per_degree);
+    let (edges_b, points_b) = features(&lightness(&b), pixels_per_degree);
+
+    let errors = (0..a.pixels().len()).map(|i| {
+        let difference = hyab(color_a.pixels()[i], color_b.pixels()[i]).powf(QC);
+        let color = if difference < PC * cmax {
+            PT / (PC * cmax) * difference
+        } else {
+            PT + (difference - PC * cmax) / (cmax - PC * cmax) * (1.0 - PT)
+        };
+
+        let edge = (edges_a.pixels()[i] - edges_b.pixels()[i]).abs();
+        let point = (points_a.pixels()[i] - points_b.pixels()[i]).abs();
+        let feature = (edge.max(point) / 2f32.sqrt()).powf(QF);
+
+        color.min(1.0).powf(1.0 - feature)
+    });
+
+    Framebuffer::from_pixels(a.width(), a.height(), errors.collect())
+}
+
+// Map errors in [0, 1] to the magma color map, as display values
+// Polynomial fit by Matt Zucker
+pub fn false_color(errors: &Framebuffer<f32>) -> Framebuffer<Vector3> {
+    const C: [Vector3; 7] = [
+        Vector3 {
+            x: -0.002_136_485,
+            y: -0.000_749_655,
+            z: -0.005_386_128,
+        },
+        Vector3 {
+            x: 0.251_660_54,
+            y: 0.677_523_24,
+            z: 2.494_026_6,
+        },
+        Vector3 {
+            x: 8.353_717,
+            y: -3.577_719_5,
+            z: 0.314_467_9,
+        },
+        Vector3 {
+            x: -27.668_733,
+            y: 14.264_731,
+            z: -13.649_213,
+        },
+        Vector3 {
+            x: 52.176_14,
+            y: -27.943_606,
+            z: 12.944_169,
+        },
+        Vector3 {
+            x: -50.768_525,
+            y: 29.046_583,
+            z: 4.234_153,
+        },
+        Vector3 {
+            x: 18.655_705,
+            y: -11.489_774,
+            z: -5.601_961_5,
+        },
+    ];
+
+    errors.map(|e| {
+        let t = e.clamp(0.0, 1.0);
+        let col = C
+            .iter()
+            .rev()
+            .fold(Vector3::default(), |sum, &c| sum * t + c);
+        clamp(col)
+    })
+}
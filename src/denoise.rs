@@ -0,0 +1,221 @@
+use crate::image::{luminance, Framebuffer};
+use crate::vector::Vector3;
+use std::thread;
+
+// B3 spline weights of the 5x5 a-trous kernel
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// Below this an albedo channel is too dark to divide by
+const MIN_ALBEDO: f32 = 1e-3;
+
+#[derive(Debug, Copy, Clone)]
+pub struct DenoiseSettings {
+    // Number of filter passes, each doubling the kernel footprint
+    pub iterations: usize,
+    // Tolerance to color differences, in tonemapped units, halved every pass
+    pub sigma_color: f32,
+    // Exponent of the normal similarity, higher keeps edges sharper
+    pub sigma_normal: f32,
+    // Tolerated relative depth change per pixel of distance
+    pub sigma_depth: f32,
+    // Tolerance to albedo differences
+    pub sigma_albedo: f32,
+    pub threads: usize,
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> Self {
+        DenoiseSettings {
+            iterations: 5,
+            sigma_color: 0.6,
+            sigma_normal: 64.0,
+            sigma_depth: 0.05,
+            sigma_albedo: 0.2,
+            threads: 1,
+        }
+    }
+}
+
+// Feature buffers steering the filter, any of them may be missing
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Guides<'a> {
+    pub albedo: Option<&'a Framebuffer<Vector3>>,
+    pub normal: Option<&'a Framebuffer<Vector3>>,
+    pub depth: Option<&'a Framebuffer<f32>>,
+}
+
+// How similar two pixels are, from 0 to 1, according to the guides
+fn guide_weight(
+    guides: &Guides,
+    settings: &DenoiseSettings,
+    p: usize,
+    q: usize,
+    distance: f32,
+) -> f32 {
+    let mut weight = 1.0;
+
+    if let Some(normal) = guides.normal {
+        let (np, nq) = (normal.pixels()[p], normal.pixels()[q]);
+
+        // Rays that escaped have no normal and only match each other
+        let (escaped_p, escaped_q) = (np.squared_length() < 1e-6, nq.squared_length() < 1e-6);
+        if escaped_p != escaped_q {
+            return 0.0;
+        }
+
+        // Normals averaged over a pixel are shorter than 1 at edges
+        if !escaped_p {
+            let cosine = np.normalize().dot(nq.normalize());
+            weight *= cosine.max(0.0).powf(settings.sigma_normal);
+        }
+    }
+
+    if let Some(depth) = guides.depth {
+        let (dp, dq) = (depth.pixels()[p], depth.pixels()[q]);
+        let tolerance = settings.sigma_depth * dp.max(dq) * distance + 1e-6;
+        weight *= (-(dp - dq).abs() / tolerance).exp();
+    }
+
+    if let Some(albedo) = guides.albedo {
+        let d = albedo.pixels()[p] - albedo.pixels()[q];
+        weight *= (-d.squared_length() / (settings.sigma_albedo * settings.sigma_albedo)).exp();
+    }
+
+    weight
+}
+
+// Compress HDR values so the color tolerance does not depend on brightness
+fn compress(col: Vector3) -> Vector3 {
+    col / (1.0 + luminance(col).max(0.0))
+}
+
+// One edge-avoiding a-trous pass with holes of `step` pixels
+fn filter_rows(
+    input: &Framebuffer<Vector3>,
+    guides: &Guides,
+    settings: &DenoiseSettings,
+    step: usize,
+    sigma_color: f32,
+    (y0, output): (usize, &mut [Vector3]),
+) {
+    let width = input.width();
+    let height = input.height();
+
+    for (index, out) in output.iter_mut().enumerate() {
+        let (x, y) = (index % width, y0 + index / width);
+        let p = y * width + x;
+        let cp = compress(input.pixels()[p]);
+
+        let mut sum = Vector3::default();
+        let mut total = 0.0;
+
+        for (j, ky) in KERNEL.iter().enumerate() {
+            let dy = (j as isize - 2) * step as isize;
+            let qy = y as isize + dy;
+            if qy < 0 || qy >= height as isize {
+                continue;
+            }
+
+            for (i, kx) in KERNEL.iter().enumerate() {
+                let dx = (i as isize - 2) * step as isize;
+                let qx = x as isize + dx;
+                if qx < 0 || qx >= width as isize {
+                    continue;
+                }
+
+                let q = qy as usize * width + qx as usize;
+                let distance = ((dx * dx + dy * dy) as f32).sqrt();
+
+                let dc = compress(input.pixels()[q]) - cp;
+                let color = (-dc.squared_length() / (sigma_color * sigma_color)).exp();
+
+                let w = kx * ky * color * guide_weight(guides, settings, p, q, distance);
+                sum += input.pixels()[q] * w;
+                total += w;
+            }
+        }
+
+        // The center pixel always has a weight of at least 9/64
+        *out = sum / total;
+    }
+}
+
+fn filter(
+    input: &Framebuffer<Vector3>,
+    guides: &Guides,
+    settings: &DenoiseSettings,
+    step: usize,
+    sigma_color: f32,
+) -> Framebuffer<Vector3> {
+    let mut output = Framebuffer::new(input.width(), input.height());
+    let width = input.width().max(1);
+    let rows = input.height().div_ceil(settings.threads.max(1)).max(1);
+
+    // Every thread filters a band of rows
+    thread::scope(|s| {
+        for (band, pixels) in output.pixels_mut().chunks_mut(rows * width).enumerate() {
+            s.spawn(move || {
+                filter_rows(
+                    input,
+                    guides,
+                    settings,
+                    step,
+                    sigma_color,
+                    (band * rows, pixels),
+                )
+            });
+        }
+    });
+
+    output
+}
+
+// Remove noise from a radiance image with an edge-avoiding a-trous wavelet filter
+// Dividing out the albedo first keeps textures sharp, only the lighting gets filtered
+pub fn denoise(
+    image: &Framebuffer<Vector3>,
+    guides: &Guides,
+    settings: &DenoiseSettings,
+) -> Framebuffer<Vector3> {
+    let safe_albedo = |a: Vector3| {
+        Vector3::new(
+            if a.x < MIN_ALBEDO { 1.0 } else { a.x },
+            if a.y < MIN_ALBEDO { 1.0 } else { a.y },
+            if a.z < MIN_ALBEDO { 1.0 } else { a.z },
+        )
+    };
+
+    let mut lighting = match guides.albedo {
+        Some(albedo) => Framebuffer::from_pixels(
+            image.width(),
+            image.height(),
+            image
+                .pixels()
+                .iter()
+                .zip(albedo.pixels())
+                .map(|(&c, &a)| c / safe_albedo(a))
+                .collect(),
+        ),
+        None => image.clone(),
+    };
+
+    let mut sigma_color = settings.sigma_color;
+    for i in 0..settings.iterations {
+        lighting = filter(&lighting, guides, settings, 1 << i, sigma_color);
+        sigma_color *= 0.5;
+    }
+
+    match guides.albedo {
+        Some(albedo) => Framebuffer::from_pixels(
+            image.width(),
+            image.height(),
+            lighting
+                .pixels()
+                .iter()
+                .zip(albedo.pixels())
+                .map(|(&c, &a)| c * safe_albedo(a))
+                .collect(),
+        ),
+        None => lighting,
+    }
+}
@@ -0,0 +1,299 @@
+use crate::image::{self, luminance, Framebuffer};
+use crate::sampler::Sampler;
+use crate::vector::Vector3;
+use std::f32::consts::PI;
+use std::io;
+use std::path::Path;
+
+// Light arriving from infinitely far away, seen by rays that escape the scene
+pub trait Environment: Send + Sync {
+    // Radiance coming from a unit direction
+    fn radiance(&self, direction: Vector3) -> Vector3;
+
+    // Pick a unit direction towards the light, returning it with its radiance and the
+    // density per solid angle of picking it
+    // None for environments left to the rays that scatter into them
+    fn sample(&self, _sampler: &mut dyn Sampler) -> Option<(Vector3, Vector3, f32)> {
+        None
+    }
+
+    // Density of sample picking a unit direction
+    fn pdf(&self, _direction: Vector3) -> f32 {
+        0.0
+    }
+}
+
+// The same color in every direction
+pub struct Constant {
+    color: Vector3,
+}
+
+impl Constant {
+    pub fn new(color: Vector3) -> Self {
+        Constant { color }
+    }
+}
+
+impl Environment for Constant {
+    fn radiance(&self, _direction: Vector3) -> Vector3 {
+        self.color
+    }
+}
+
+// A blend from one color straight down to another straight up
+pub struct Gradient {
+    bottom: Vector3,
+    top: Vector3,
+}
+
+impl Gradient {
+    pub fn new(bottom: Vector3, top: Vector3) -> Self {
+        Gradient { bottom, top }
+    }
+
+    // White fading to light blue overhead
+    pub fn sky() -> Self {
+        Gradient::new(Vector3::new(1.0, 1.0, 1.0), Vector3::new(0.5, 0.7, 1.0))
+    }
+}
+
+impl Environment for Gradient {
+    fn radiance(&self, direction: Vector3) -> Vector3 {
+        let t = 0.5 * (direction.y + 1.0);
+        self.bottom * (1.0 - t) + self.top * t
+    }
+}
+
+// A latitude-longitude image of the surroundings, +y up
+// The middle of the image lies towards -z, where the cameras look by default
+pub struct EnvironmentMap {
+    image: Framebuffer<Vector3>,
+    // Turn about the vertical axis in radians
+    rotation: f32,
+    intensity: f32,
+    // Pick image coordinates in proportion to the light coming from their pixel: a row
+    // first, then a pixel along it
+    rows: Distribution1D,
+    pixels: Vec<Distribution1D>,
+}
+
+impl EnvironmentMap {
+    // Rotation is in degrees, counterclockwise seen from above
+    pub fn new(image: Framebuffer<Vector3>, rotation: f32, intensity: f32) -> Self {
+        assert!(
+            image.width() > 0 && image.height() > 0,
+            "an environment map needs pixels"
+        );
+
+        // Light of every pixel's area, which the bilinear lookup blends from its
+        // neighbours as well, so pixels next to a bright one are picked often enough
+        let (width, height) = (image.width(), image.height());
+        let weights = [(-1, 0.125), (0, 0.75), (1, 0.125)];
+        let light = |x: usize, y: usize| {
+            let mut sum = 0.0;
+            for &(dy, wy) in &weights {
+                let y = (y as isize + dy).clamp(0, height as isize - 1) as usize;
+                for &(dx, wx) in &weights {
+                    let x = (x as isize + dx).rem_euclid(width as isize) as usize;
+                    sum += wx * wy * luminance(image.get(x, y)).max(0.0);
+                }
+            }
+            sum
+        };
+
+        // Rows near the poles cover less of the sphere
+        let pixels: Vec<Distribution1D> = (0..height)
+            .map(|y| {
+                let sin_theta = (PI * (y as f32 + 0.5) / height as f32).sin();
+                Distribution1D::new((0..width).map(|x| light(x, y) * sin_theta).collect())
+            })
+            .collect();
+        let rows = Distribution1D::new(pixels.iter().map(Distribution1D::total).collect());
+
+        EnvironmentMap {
+            image,
+            rotation: rotation.to_radians(),
+            intensity,
+            rows,
+            pixels,
+        }
+    }
+
+    // Load an HDR or PFM image, or any other format image::load reads
+    pub fn load<P: AsRef<Path>>(path: P, rotation: f32, intensity: f32) -> io::Result<Self> {
+        let image = image::load(path)?;
+        if image.width() == 0 || image.height() == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "image is empty"));
+        }
+
+        Ok(EnvironmentMap::new(image, rotation, intensity))
+    }
+
+    // Image coordinates in [0, 1] of a unit direction
+    fn uv(&self, direction: Vector3) -> (f32, f32) {
+        let phi = direction.x.atan2(-direction.z) - self.rotation;
+        let theta = direction.y.clamp(-1.0, 1.0).acos();
+
+        ((phi / (2.0 * PI) + 0.5).rem_euclid(1.0), theta / PI)
+    }
+
+    // Unit direction of image coordinates, the inverse of uv
+    fn direction(&self, (u, v): (f32, f32)) -> Vector3 {
+        let phi = (u - 0.5) * 2.0 * PI + self.rotation;
+        let theta = v * PI;
+
+        Vector3::new(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        )
+    }
+
+    // Bilinear lookup, wrapping around horizontally
+    fn lookup(&self, (u, v): (f32, f32)) -> Vector3 {
+        let (width, height) = (self.image.width(), self.image.height());
+        let x = u * width as f32 - 0.5;
+        let y = (v * height as f32 - 0.5).clamp(0.0, (height - 1) as f32);
+
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let column = |x: f32| (x as isize).rem_euclid(width as isize) as usize;
+        let (x0, x1) = (column(x0), column(x0 + 1.0));
+        let (y0, y1) = (y0 as usize, (y0 as usize + 1).min(height - 1));
+
+        let top = self.image.get(x0, y0) * (1.0 - fx) + self.image.get(x1, y0) * fx;
+        let bottom = self.image.get(x0, y1) * (1.0 - fx) + self.image.get(x1, y1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
+
+// Where to load an environment map from and how to place it
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapFile {
+    pub path: String,
+    // Degrees about the vertical axis
+    pub rotation: f32,
+    pub intensity: f32,
+}
+
+impl MapFile {
+    pub fn load(&self) -> io::Result<EnvironmentMap> {
+        EnvironmentMap::load(&self.path, self.rotation, self.intensity)
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn radiance(&self, direction: Vector3) -> Vector3 {
+        self.lookup(self.uv(direction)) * self.intensity
+    }
+
+    // Pick a row by the light it gives, then a pixel within it
+    fn sample(&self, sampler: &mut dyn Sampler) -> Option<(Vector3, Vector3, f32)> {
+        if self.rows.total() <= 0.0 || self.intensity <= 0.0 {
+            return None;
+        }
+
+        let (a, b) = sampler.get_2d();
+        let (v, row_pdf, row) = self.rows.sample(b);
+        let (u, pixel_pdf, _) = self.pixels[row].sample(a);
+
+        // From density over the image to density over directions
+        let sin_theta = (v * PI).sin();
+        if sin_theta <= 0.0 {
+            return None;
+        }
+        let pdf = row_pdf * pixel_pdf / (2.0 * PI * PI * sin_theta);
+
+        let direction = self.direction((u, v));
+        Some((direction, self.radiance(direction), pdf))
+    }
+
+    fn pdf(&self, direction: Vector3) -> f32 {
+        if self.rows.total() <= 0.0 || self.intensity <= 0.0 {
+            return 0.0;
+        }
+
+        let (u, v) = self.uv(direction);
+        let sin_theta = (v * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        let row = ((v * self.image.height() as f32) as usize).min(self.image.height() - 1);
+        self.rows.pdf(v) * self.pixels[row].pdf(u) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+// Piecewise constant density over [0, 1), each of the equal pieces proportional to its
+// weight
+#[derive(Debug, Clone)]
+pub struct Distribution1D {
+    weights: Vec<f32>,
+    // Running sums of the normalized weights, one more than there are pieces
+    cdf: Vec<f32>,
+    total: f32,
+}
+
+impl Distribution1D {
+    // All weights zero gives a uniform density, but a total of zero
+    pub fn new(weights: Vec<f32>) -> Self {
+        assert!(
+            !weights.is_empty(),
+            "a distribution needs at least one piece"
+        );
+
+        let total: f64 = weights.iter().map(|&w| w.max(0.0) as f64).sum();
+        let mut cdf = Vec::with_capacity(weights.len() + 1);
+        let mut sum = 0.0;
+        cdf.push(0.0);
+        for (i, &w) in weights.iter().enumerate() {
+            sum += if total > 0.0 {
+                w.max(0.0) as f64 / total
+            } else {
+                1.0 / weights.len() as f64
+            };
+            cdf.push(if i + 1 == weights.len() {
+                1.0
+            } else {
+                sum as f32
+            });
+        }
+
+        Distribution1D {
+            weights,
+            cdf,
+            total: total as f32,
+        }
+    }
+
+    // Sum of the weights
+    pub fn total(&self) -> f32 {
+        self.total
+    }
+
+    // Map a uniform number in [0, 1) to a point of the density
+    // Returns the point, the density there and the index of its piece
+    pub fn sample(&self, u: f32) -> (f32, f32, usize) {
+        let n = self.weights.len();
+
+        // The first piece ending past u, pieces of zero weight are never chosen
+        let index = (self.cdf.partition_point(|&c| c <= u) - 1).min(n - 1);
+        let (start, end) = (self.cdf[index], self.cdf[index + 1]);
+        let offset = if end > start {
+            (u - start) / (end - start)
+        } else {
+            0.0
+        };
+
+        let x = ((index as f32 + offset) / n as f32).min(1.0 - f32::EPSILON);
+        (x, self.pdf(x), index)
+    }
+
+    // Density at a point in [0, 1)
+    pub fn pdf(&self, x: f32) -> f32 {
+        let n = self.weights.len();
+        let index = ((x * n as f32) as usize).min(n - 1);
+
+        (self.cdf[index + 1] - self.cdf[index]) * n as f32
+    }
+}
@@ -0,0 +1,137 @@
+use std::f32::consts::PI;
+use std::str::FromStr;
+
+// Mitchell and Lanczos have negative lobes, which sharpen but can ring below zero around
+// bright edges, or nearly cancel out a pixel's weights when it has few samples
+// Accumulator::resolve clamps the radiance to zero and keeps the weights of each pixel
+// at a quarter of their absolute sum or more, so neither shows up as wild values
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FilterKind {
+    // Every sample within the radius counts the same
+    Box,
+    // Weights falling off linearly to zero at the radius
+    Tent,
+    // Gaussian with a standard deviation of a third of the radius, shifted to end at zero
+    Gaussian,
+    // Mitchell-Netravali cubic with B = C = 1/3, stretched over the radius
+    Mitchell,
+    // Sinc windowed by a wider sinc, one lobe per pixel of radius
+    Lanczos,
+}
+
+impl FilterKind {
+    pub const NAMES: &'static [&'static str] = &["box", "tent", "gaussian", "mitchell", "lanczos"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FilterKind::Box => "box",
+            FilterKind::Tent => "tent",
+            FilterKind::Gaussian => "gaussian",
+            FilterKind::Mitchell => "mitchell",
+            FilterKind::Lanczos => "lanczos",
+        }
+    }
+
+    // Radius in pixels used when none is given
+    pub fn default_radius(self) -> f32 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 3.0,
+        }
+    }
+}
+
+impl FromStr for FilterKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "box" => Ok(FilterKind::Box),
+            "tent" => Ok(FilterKind::Tent),
+            "gaussian" => Ok(FilterKind::Gaussian),
+            "mitchell" => Ok(FilterKind::Mitchell),
+            "lanczos" => Ok(FilterKind::Lanczos),
+            _ => Err(()),
+        }
+    }
+}
+
+// Pixel reconstruction filter, weighting every sample into the pixels around it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Filter {
+    pub kind: FilterKind,
+    // Half width of the filter's support, in pixels
+    pub radius: f32,
+}
+
+impl Default for Filter {
+    // A box over a single pixel, the plain average of its own samples
+    fn default() -> Self {
+        Filter::new(FilterKind::Box)
+    }
+}
+
+impl Filter {
+    pub fn new(kind: FilterKind) -> Self {
+        Filter {
+            kind,
+            radius: kind.default_radius(),
+        }
+    }
+
+    // Weight of a sample at an offset from a pixel center, zero outside the radius
+    // Mitchell and Lanczos have negative lobes
+    pub fn weight(&self, dx: f32, dy: f32) -> f32 {
+        self.weight_1d(dx) * self.weight_1d(dy)
+    }
+
+    fn weight_1d(&self, x: f32) -> f32 {
+        let x = x.abs();
+        if x >= self.radius {
+            return 0.0;
+        }
+
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => 1.0 - x / self.radius,
+            FilterKind::Gaussian => {
+                let sigma = self.radius / 3.0;
+                let gaussian = |x: f32| (-x * x / (2.0 * sigma * sigma)).exp();
+                gaussian(x) - gaussian(self.radius)
+            }
+            FilterKind::Mitchell => mitchell(2.0 * x / self.radius),
+            FilterKind::Lanczos => sinc(x) * sinc(x / self.radius),
+        }
+    }
+}
+
+// Mitchell-Netravali cubic on [0, 2] with B = C = 1/3
+fn mitchell(x: f32) -> f32 {
+    const B: f32 = 1.0 / 3.0;
+    const C: f32 = 1.0 / 3.0;
+
+    let x2 = x * x;
+    let x3 = x2 * x;
+
+    let value = if x < 1.0 {
+        (12.0 - 9.0 * B - 6.0 * C) * x3 + (-18.0 + 12.0 * B + 6.0 * C) * x2 + (6.0 - 2.0 * B)
+    } else {
+        (-B - 6.0 * C) * x3
+            + (6.0 * B + 30.0 * C) * x2
+            + (-12.0 * B - 48.0 * C) * x
+            + (8.0 * B + 24.0 * C)
+    };
+
+    value / 6.0
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
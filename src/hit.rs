@@ -1,28 +1,73 @@
 use crate::aabb::Aabb;
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vector::Vector3;
-use std::rc::Rc;
+use std::sync::Arc;
 
 pub struct HitRecord {
     pub t: f32,
     pub p: Vector3,
     pub normal: Vector3,
-    pub material: Rc<dyn Material>,
+    pub material: Arc<dyn Material>,
+    // ID of the scene object that was hit, 0 if it has none
+    pub object_id: u32,
 }
 
 impl HitRecord {
-    pub fn new(t: f32, p: Vector3, normal: Vector3, material: Rc<dyn Material>) -> Self {
+    pub fn new(t: f32, p: Vector3, normal: Vector3, material: Arc<dyn Material>) -> Self {
         HitRecord {
             t,
             p,
             normal,
             material,
+            object_id: 0,
         }
     }
 }
 
-pub trait Hit {
+pub trait Hit: Send + Sync {
     fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
     fn bounding_box(&self, t0: f32, t1: f32) -> Option<Aabb>;
+
+    // Pick a unit direction from a point towards the object as it is at a time, with the
+    // density per solid angle of picking it, so lights can be sampled directly
+    // None for objects that cannot be sampled
+    fn sample_direction(
+        &self,
+        _origin: Vector3,
+        _time: f32,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<(Vector3, f32)> {
+        None
+    }
+
+    // Density of sample_direction picking a unit direction, zero where it misses
+    fn direction_pdf(&self, _origin: Vector3, _direction: Vector3, _time: f32) -> f32 {
+        0.0
+    }
+}
+
+// Shared objects, such as lights kept in a list of their own as well as in the scene
+impl<H: Hit + ?Sized> Hit for Arc<H> {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        (**self).hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<Aabb> {
+        (**self).bounding_box(t0, t1)
+    }
+
+    fn sample_direction(
+        &self,
+        origin: Vector3,
+        time: f32,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Vector3, f32)> {
+        (**self).sample_direction(origin, time, sampler)
+    }
+
+    fn direction_pdf(&self, origin: Vector3, direction: Vector3, time: f32) -> f32 {
+        (**self).direction_pdf(origin, direction, time)
+    }
 }
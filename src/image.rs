@@ -0,0 +1,855 @@
+use crate::tonemap::{Tonemap, Transfer};
+use crate::vector::Vector3;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+// An 8 bit per channel RGB pixel
+pub type Rgb8 = [u8; 3];
+
+// A 2D grid of pixels, stored row by row from the top of the image
+#[derive(Debug, Clone, PartialEq)]
+pub struct Framebuffer<T> {
+    width: usize,
+    height: usize,
+    pixels: Vec<T>,
+}
+
+impl<T: Copy + Default> Framebuffer<T> {
+    pub fn new(width: usize, height: usize) -> Self {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![T::default(); width * height],
+        }
+    }
+
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<T>) -> Self {
+        assert_eq!(
+            pixels.len(),
+            width * height,
+            "pixel count does not match size"
+        );
+
+        Framebuffer {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> T {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, pixel: T) {
+        self.pixels[y * self.width + x] = pixel;
+    }
+
+    pub fn pixels(&self) -> &[T] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [T] {
+        &mut self.pixels
+    }
+
+    pub fn row(&self, y: usize) -> &[T] {
+        &self.pixels[y * self.width..(y + 1) * self.width]
+    }
+
+    pub fn row_mut(&mut self, y: usize) -> &mut [T] {
+        &mut self.pixels[y * self.width..(y + 1) * self.width]
+    }
+
+    // Apply a function to every pixel, producing a new framebuffer
+    pub fn map<U, F>(&self, f: F) -> Framebuffer<U>
+    where
+        F: Fn(T) -> U,
+    {
+        Framebuffer {
+            width: self.width,
+            height: self.height,
+            pixels: self.pixels.iter().map(|&p| f(p)).collect(),
+        }
+    }
+
+    // Copy out the width x height block of pixels starting at x0, y0
+    pub fn crop(&self, (x0, y0): (usize, usize), width: usize, height: usize) -> Self {
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in y0..y0 + height {
+            pixels.extend_from_slice(&self.row(y)[x0..x0 + width]);
+        }
+
+        Framebuffer::from_pixels(width, height, pixels)
+    }
+}
+
+// Relative luminance of linear Rec. 709 color
+pub fn luminance(col: Vector3) -> f32 {
+    0.2126 * col.x + 0.7152 * col.y + 0.0722 * col.z
+}
+
+impl Framebuffer<Vector3> {
+    // Quantize display values in [0, 1] to 8 bits
+    pub fn to_rgb8(&self) -> Framebuffer<Rgb8> {
+        self.map(|col| {
+            // Convert to RGB, float to int casts saturate
+            [
+                (255.99 * col.x) as u8,
+                (255.99 * col.y) as u8,
+                (255.99 * col.z) as u8,
+            ]
+        })
+    }
+}
+
+impl Framebuffer<Rgb8> {
+    fn bytes(&self) -> Vec<u8> {
+        self.pixels.iter().flat_map(|p| p.iter().copied()).collect()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ImageFormat {
+    // Binary P6 portable pixmap
+    Ppm,
+    Png,
+    // Portable float map, 32 bit float per channel
+    Pfm,
+    // Radiance RGBE
+    Hdr,
+    // Single-part scanline OpenEXR, uncompressed 32 bit float
+    Exr,
+}
+
+impl ImageFormat {
+    pub const NAMES: &'static [&'static str] = &["ppm", "png", "pfm", "hdr", "exr"];
+
+    // Whether the format stores linear radiance rather than display values
+    pub fn is_hdr(self) -> bool {
+        match self {
+            ImageFormat::Ppm | ImageFormat::Png => false,
+            ImageFormat::Pfm | ImageFormat::Hdr | ImageFormat::Exr => true,
+        }
+    }
+
+    // Usual file extension of the format
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Ppm => "ppm",
+            ImageFormat::Png => "png",
+            ImageFormat::Pfm => "pfm",
+            ImageFormat::Hdr => "hdr",
+            ImageFormat::Exr => "exr",
+        }
+    }
+
+    // Guess the format from a file extension
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?;
+        extension.to_ascii_lowercase().parse().ok()
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ppm" => Ok(ImageFormat::Ppm),
+            "png" => Ok(ImageFormat::Png),
+            "pfm" => Ok(ImageFormat::Pfm),
+            "hdr" => Ok(ImageFormat::Hdr),
+            "exr" => Ok(ImageFormat::Exr),
+            _ => Err(()),
+        }
+    }
+}
+
+pub fn write_ppm<W: Write>(mut out: W, image: &Framebuffer<Rgb8>) -> io::Result<()> {
+    write!(out, "P6\n{} {}\n255\n", image.width, image.height)?;
+    out.write_all(&image.bytes())?;
+    out.flush()
+}
+
+pub fn write_png<W: Write>(out: W, image: &Framebuffer<Rgb8>) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(out, image.width as u32, image.height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header().map_err(io::Error::from)?;
+    writer
+        .write_image_data(&image.bytes())
+        .map_err(io::Error::from)?;
+    writer.finish().map_err(io::Error::from)
+}
+
+pub fn write_pfm<W: Write>(mut out: W, image: &Framebuffer<Vector3>) -> io::Result<()> {
+    // A negative scale marks little endian data
+    write!(out, "PF\n{} {}\n-1.0\n", image.width, image.height)?;
+
+    // Rows are stored from the bottom of the image
+    for y in (0..image.height).rev() {
+        for p in image.row(y) {
+            out.write_all(&p.x.to_le_bytes())?;
+            out.write_all(&p.y.to_le_bytes())?;
+            out.write_all(&p.z.to_le_bytes())?;
+        }
+    }
+
+    out.flush()
+}
+
+// Encode a color as a shared exponent and three 8 bit mantissas
+fn to_rgbe(col: Vector3) -> [u8; 4] {
+    let v = col.x.max(col.y).max(col.z);
+    if v < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+
+    // v = m * 2^e with m in [0.5, 1)
+    let e = v.log2().floor() as i32 + 1;
+    let scale = 256.0 / 2f32.powi(e);
+
+    [
+        (col.x.max(0.0) * scale).min(255.0) as u8,
+        (col.y.max(0.0) * scale).min(255.0) as u8,
+        (col.z.max(0.0) * scale).min(255.0) as u8,
+        (e + 128) as u8,
+    ]
+}
+
+pub fn write_hdr<W: Write>(mut out: W, image: &Framebuffer<Vector3>) -> io::Result<()> {
+    write!(
+        out,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        image.height, image.width
+    )?;
+
+    // Flat, run length encoding is optional for readers
+    for p in image.pixels() {
+        out.write_all(&to_rgbe(*p))?;
+    }
+
+    out.flush()
+}
+
+// Append an OpenEXR header attribute
+fn exr_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+// One named channel of a multi-channel image
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channel {
+    pub name: String,
+    pub values: Framebuffer<f32>,
+}
+
+impl Channel {
+    // Split a color image into three channels named layer.X, layer.Y and layer.Z,
+    // or just X, Y and Z if the layer name is empty
+    pub fn split(layer: &str, names: [&str; 3], image: &Framebuffer<Vector3>) -> Vec<Channel> {
+        (0..3)
+            .map(|i| Channel {
+                name: if layer.is_empty() {
+                    names[i].to_string()
+                } else {
+                    format!("{}.{}", layer, names[i])
+                },
+                values: image.map(|p| p[i]),
+            })
+            .collect()
+    }
+}
+
+pub fn write_exr<W: Write>(out: W, image: &Framebuffer<Vector3>) -> io::Result<()> {
+    write_exr_channels(out, &Channel::split("", ["R", "G", "B"], image))
+}
+
+// Write any number of same-sized float channels, such as the layers of AOVs
+pub fn write_exr_channels<W: Write>(mut out: W, channels: &[Channel]) -> io::Result<()> {
+    const FLOAT: i32 = 2;
+
+    let image_width = channels.first().map_or(0, |c| c.values.width);
+    let image_height = channels.first().map_or(0, |c| c.values.height);
+    assert!(
+        channels
+            .iter()
+            .all(|c| c.values.width == image_width && c.values.height == image_height),
+        "channel sizes do not match"
+    );
+
+    let width = image_width as i32;
+    let height = image_height as i32;
+
+    // Channels are stored in alphabetical order
+    let mut channels: Vec<&Channel> = channels.iter().collect();
+    channels.sort_by(|a, b| a.name.cmp(&b.name));
+
+    // Magic number and version 2, single-part scanline
+    let mut header = Vec::new();
+    header.extend_from_slice(&20000630i32.to_le_bytes());
+    header.extend_from_slice(&2i32.to_le_bytes());
+
+    let mut channel_list = Vec::new();
+    for channel in &channels {
+        channel_list.extend_from_slice(channel.name.as_bytes());
+        channel_list.push(0);
+        channel_list.extend_from_slice(&FLOAT.to_le_bytes());
+        channel_list.extend_from_slice(&[0, 0, 0, 0]); // pLinear and reserved
+        channel_list.extend_from_slice(&1i32.to_le_bytes()); // x sampling
+        channel_list.extend_from_slice(&1i32.to_le_bytes()); // y sampling
+    }
+    channel_list.push(0);
+
+    let mut window = Vec::new();
+    for v in &[0, 0, width - 1, height - 1] {
+        window.extend_from_slice(&v.to_le_bytes());
+    }
+
+    let mut screen_center = Vec::new();
+    screen_center.extend_from_slice(&0f32.to_le_bytes());
+    screen_center.extend_from_slice(&0f32.to_le_bytes());
+
+    exr_attribute(&mut header, "channels", "chlist", &channel_list);
+    exr_attribute(&mut header, "compression", "compression", &[0]);
+    exr_attribute(&mut header, "dataWindow", "box2i", &window);
+    exr_attribute(&mut header, "displayWindow", "box2i", &window);
+    exr_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    exr_attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    exr_attribute(&mut header, "screenWindowCenter", "v2f", &screen_center);
+    exr_attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    header.push(0);
+    out.write_all(&header)?;
+
+    // Offset table, one uncompressed scanline per chunk
+    let header_size = header.len() as u64;
+    let table_size = 8 * image_height as u64;
+    let line_size = (channels.len() * 4 * image_width) as u64;
+    for y in 0..image_height as u64 {
+        let offset = header_size + table_size + y * (8 + line_size);
+        out.write_all(&offset.to_le_bytes())?;
+    }
+
+    for y in 0..image_height {
+        out.write_all(&(y as i32).to_le_bytes())?;
+        out.write_all(&(line_size as i32).to_le_bytes())?;
+
+        for channel in &channels {
+            for v in channel.values.row(y) {
+                out.write_all(&v.to_le_bytes())?;
+            }
+        }
+    }
+
+    out.flush()
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Read a whitespace separated header token of a PPM or PFM file
+// The single whitespace byte ending it is consumed as well, and comments from a # to the
+// end of the line are skipped
+fn read_token<R: BufRead>(input: &mut R) -> io::Result<String> {
+    let mut token = Vec::new();
+    let mut comment = false;
+
+    for byte in input.bytes() {
+        let byte = byte?;
+        if comment {
+            comment = byte != b'\n' && byte != b'\r';
+            continue;
+        }
+        if byte == b'#' && token.is_empty() {
+            comment = true;
+            continue;
+        }
+        if byte.is_ascii_whitespace() {
+            if token.is_empty() {
+                continue;
+            }
+            break;
+        }
+        token.push(byte);
+    }
+
+    String::from_utf8(token).map_err(|_| invalid("header is not ASCII"))
+}
+
+fn read_size<R: BufRead>(input: &mut R) -> io::Result<(usize, usize)> {
+    let width = read_token(input)?
+        .parse()
+        .map_err(|_| invalid("bad width"))?;
+    let height = read_token(input)?
+        .parse()
+        .map_err(|_| invalid("bad height"))?;
+    Ok((width, height))
+}
+
+pub fn read_pfm<R: BufRead>(mut input: R) -> io::Result<Framebuffer<Vector3>> {
+    let channels = match read_token(&mut input)?.as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(invalid("not a PFM file")),
+    };
+
+    let (width, height) = read_size(&mut input)?;
+    let scale: f32 = read_token(&mut input)?
+        .parse()
+        .map_err(|_| invalid("bad scale"))?;
+
+    let mut image = Framebuffer::new(width, height);
+    let mut bytes = [0; 4];
+    let mut value = || -> io::Result<f32> {
+        input.read_exact(&mut bytes)?;
+        Ok(if scale < 0.0 {
+            f32::from_le_bytes(bytes)
+        } else {
+            f32::from_be_bytes(bytes)
+        })
+    };
+
+    for y in (0..height).rev() {
+        for p in image.row_mut(y) {
+            *p = if channels == 3 {
+                Vector3::new(value()?, value()?, value()?)
+            } else {
+                let v = value()?;
+                Vector3::new(v, v, v)
+            };
+        }
+    }
+
+    Ok(image)
+}
+
+// Decode a shared exponent color
+fn from_rgbe(rgbe: [u8; 4]) -> Vector3 {
+    if rgbe[3] == 0 {
+        return Vector3::default();
+    }
+
+    let scale = 2f32.powi(rgbe[3] as i32 - 136);
+    Vector3::new(rgbe[0] as f32, rgbe[1] as f32, rgbe[2] as f32) * scale
+}
+
+// Read one scanline of RGBE pixels, flat or in the run length encoding of newer files
+// where every component is stored separately
+fn read_rgbe_scanline<R: Read>(input: &mut R, scanline: &mut [[u8; 4]]) -> io::Result<()> {
+    let width = scanline.len();
+    let mut first = [0; 4];
+    input.read_exact(&mut first)?;
+
+    let encoded = (8..0x8000).contains(&width)
+        && first[0] == 2
+        && first[1] == 2
+        && ((first[2] as usize) << 8 | first[3] as usize) == width;
+    if !encoded {
+        scanline[0] = first;
+        for pixel in &mut scanline[1..] {
+            input.read_exact(pixel)?;
+        }
+        return Ok(());
+    }
+
+    let mut byte = [0; 1];
+    for component in 0..4 {
+        let mut x = 0;
+        while x < width {
+            input.read_exact(&mut byte)?;
+
+            // Counts above 128 repeat the next byte, others are followed by literal bytes
+            let (count, run) = match byte[0] {
+                count if count > 128 => ((count - 128) as usize, true),
+                count => (count as usize, false),
+            };
+            if count == 0 || x + count > width {
+                return Err(invalid("bad run length"));
+            }
+
+            if run {
+                input.read_exact(&mut byte)?;
+            }
+            for pixel in &mut scanline[x..x + count] {
+                if !run {
+                    input.read_exact(&mut byte)?;
+                }
+                pixel[component] = byte[0];
+            }
+            x += count;
+        }
+    }
+
+    Ok(())
+}
+
+// Read a Radiance RGBE image stored top to bottom
+pub fn read_hdr<R: BufRead>(mut input: R) -> io::Result<Framebuffer<Vector3>> {
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+    if !line.starts_with("#?") {
+        return Err(invalid("not a Radiance HDR file"));
+    }
+
+    // Header variables end at an empty line
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Err(invalid("header has no end"));
+        }
+
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" {
+            return Err(invalid("only RGBE pixels are supported"));
+        }
+    }
+
+    line.clear();
+    input.read_line(&mut line)?;
+    let size: Vec<&str> = line.split_whitespace().collect();
+    let (height, width) = match size[..] {
+        ["-Y", height, "+X", width] => (height.parse(), width.parse()),
+        _ => {
+            return Err(invalid(
+                "only top to bottom, left to right images are supported",
+            ))
+        }
+    };
+    let height = height.map_err(|_| invalid("bad height"))?;
+    let width = width.map_err(|_| invalid("bad width"))?;
+
+    let mut image = Framebuffer::new(width, height);
+    let mut scanline = vec![[0; 4]; width];
+    for y in 0..height {
+        read_rgbe_scanline(&mut input, &mut scanline)?;
+        for (p, &rgbe) in image.row_mut(y).iter_mut().zip(&scanline) {
+            *p = from_rgbe(rgbe);
+        }
+    }
+
+    Ok(image)
+}
+
+// Read a binary P6 or ASCII P3 pixmap as display values in [0, 1]
+pub fn read_ppm<R: BufRead>(mut input: R) -> io::Result<Framebuffer<Vector3>> {
+    let ascii = match read_token(&mut input)?.as_str() {
+        "P6" => false,
+        "P3" => true,
+        _ => return Err(invalid("not a PPM file")),
+    };
+
+    let (width, height) = read_size(&mut input)?;
+    let max: u32 = read_token(&mut input)?
+        .parse()
+        .map_err(|_| invalid("bad maximum value"))?;
+    if max == 0 || max > 65535 {
+        return Err(invalid("bad maximum value"));
+    }
+
+    // ASCII values are decimal tokens, binary values above 255 take two bytes, most
+    // significant first
+    let mut bytes = [0; 2];
+    let size = if max > 255 { 2 } else { 1 };
+    let mut value = || -> io::Result<f32> {
+        let v = if ascii {
+            read_token(&mut input)?
+                .parse()
+                .ok()
+                .filter(|&v| v <= max)
+                .ok_or_else(|| invalid("bad pixel value"))?
+        } else {
+            input.read_exact(&mut bytes[..size])?;
+            if size == 2 {
+                u16::from_be_bytes(bytes) as u32
+            } else {
+                bytes[0] as u32
+            }
+        };
+        Ok(v as f32 / max as f32)
+    };
+
+    let mut image = Framebuffer::new(width, height);
+    for p in image.pixels_mut() {
+        *p = Vector3::new(value()?, value()?, value()?);
+    }
+
+    Ok(image)
+}
+
+// Read a PNG as display values in [0, 1], dropping any alpha
+pub fn read_png<R: Read>(input: R) -> io::Result<Framebuffer<Vector3>> {
+    let mut decoder = png::Decoder::new(input);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+    let mut reader = decoder.read_info().map_err(io::Error::from)?;
+    let mut bytes = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut bytes).map_err(io::Error::from)?;
+
+    let channels = info.color_type.samples();
+    let pixels = bytes[..info.buffer_size()]
+        .chunks_exact(channels)
+        .map(|p| {
+            let value = |i: usize| p[i] as f32 / 255.0;
+            match channels {
+                1 | 2 => Vector3::new(value(0), value(0), value(0)),
+                _ => Vector3::new(value(0), value(1), value(2)),
+            }
+        })
+        .collect();
+
+    Ok(Framebuffer::from_pixels(
+        info.width as usize,
+        info.height as usize,
+        pixels,
+    ))
+}
+
+// Convert an IEEE 754 half precision float
+fn half_to_f32(h: u16) -> f32 {
+    let sign = if h & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((h >> 10) & 0x1f) as i32;
+    let mantissa = (h & 0x3ff) as f32;
+
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        31 if mantissa == 0.0 => f32::INFINITY,
+        31 => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+// Read every channel of a single-part, uncompressed scanline OpenEXR file
+pub fn read_exr_channels<R: Read>(mut input: R) -> io::Result<Vec<Channel>> {
+    let mut header = [0; 8];
+    input.read_exact(&mut header)?;
+    if header[..4] != 20000630i32.to_le_bytes() {
+        return Err(invalid("not an OpenEXR file"));
+    }
+    if header[5] & 0x1a != 0 {
+        return Err(invalid(
+            "only single-part scanline OpenEXR files are supported",
+        ));
+    }
+
+    // Attributes as name, type, size and value until an empty name
+    let read_string = |input: &mut R| -> io::Result<String> {
+        let mut bytes = Vec::new();
+        let mut byte = [0];
+        loop {
+            input.read_exact(&mut byte)?;
+            if byte[0] == 0 {
+                break;
+            }
+            bytes.push(byte[0]);
+        }
+        String::from_utf8(bytes).map_err(|_| invalid("attribute name is not UTF-8"))
+    };
+
+    let mut channel_list = None;
+    let mut window = None;
+    loop {
+        let name = read_string(&mut input)?;
+        if name.is_empty() {
+            break;
+        }
+        read_string(&mut input)?;
+
+        let mut size = [0; 4];
+        input.read_exact(&mut size)?;
+        let mut value = vec![0; i32::from_le_bytes(size).max(0) as usize];
+        input.read_exact(&mut value)?;
+
+        match name.as_str() {
+            "channels" => channel_list = Some(value),
+            "compression" if value != [0] => {
+                return Err(invalid("only uncompressed OpenEXR files are supported"))
+            }
+            "dataWindow" if value.len() == 16 => {
+                let v = |i: usize| {
+                    i32::from_le_bytes([value[i], value[i + 1], value[i + 2], value[i + 3]])
+                };
+                window = Some((v(0), v(4), v(8), v(12)));
+            }
+            _ => {}
+        }
+    }
+
+    let channel_list = channel_list.ok_or_else(|| invalid("missing channel list"))?;
+    let (x0, y0, x1, y1) = window.ok_or_else(|| invalid("missing data window"))?;
+    let width = (x1 - x0 + 1).max(0) as usize;
+    let height = (y1 - y0 + 1).max(0) as usize;
+
+    // Channel entries are a name followed by 16 bytes, the pixel type first
+    let mut kinds = Vec::new();
+    let mut channels = Vec::new();
+    let mut rest = &channel_list[..];
+    while let Some(end) = rest.iter().position(|&b| b == 0) {
+        if end == 0 || rest.len() < end + 17 {
+            break;
+        }
+
+        let name = String::from_utf8_lossy(&rest[..end]).into_owned();
+        let entry = &rest[end + 1..end + 17];
+        kinds.push(i32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]));
+        if entry[8..16] != [1, 0, 0, 0, 1, 0, 0, 0] {
+            return Err(invalid("subsampled OpenEXR channels are not supported"));
+        }
+
+        channels.push(Channel {
+            name,
+            values: Framebuffer::new(width, height),
+        });
+        rest = &rest[end + 17..];
+    }
+
+    // Skip the offset table, scanlines follow it in the file
+    let mut offsets = vec![0; 8 * height];
+    input.read_exact(&mut offsets)?;
+
+    for _ in 0..height {
+        let mut line = [0; 8];
+        input.read_exact(&mut line)?;
+        let y = i32::from_le_bytes([line[0], line[1], line[2], line[3]]) - y0;
+        if y < 0 || y as usize >= height {
+            return Err(invalid("scanline outside of the data window"));
+        }
+
+        for (channel, kind) in channels.iter_mut().zip(&kinds) {
+            for p in channel.values.row_mut(y as usize) {
+                *p = match kind {
+                    // UINT
+                    0 => {
+                        let mut bytes = [0; 4];
+                        input.read_exact(&mut bytes)?;
+                        u32::from_le_bytes(bytes) as f32
+                    }
+                    // HALF
+                    1 => {
+                        let mut bytes = [0; 2];
+                        input.read_exact(&mut bytes)?;
+                        half_to_f32(u16::from_le_bytes(bytes))
+                    }
+                    // FLOAT
+                    2 => {
+                        let mut bytes = [0; 4];
+                        input.read_exact(&mut bytes)?;
+                        f32::from_le_bytes(bytes)
+                    }
+                    _ => return Err(invalid("unknown OpenEXR pixel type")),
+                };
+            }
+        }
+    }
+
+    Ok(channels)
+}
+
+// Join three channels of a layer back into a color image
+// Channels are looked up as layer.R, layer.G and layer.B, or without the prefix if
+// the layer name is empty; names may be X, Y and Z instead of R, G and B
+pub fn find_layer(channels: &[Channel], layer: &str) -> Option<Framebuffer<Vector3>> {
+    let find = |names: &[&str]| {
+        names.iter().find_map(|name| {
+            let full = if layer.is_empty() {
+                name.to_string()
+            } else {
+                format!("{}.{}", layer, name)
+            };
+            channels.iter().find(|c| c.name == full)
+        })
+    };
+
+    let r = find(&["R", "X", "Z"])?;
+    let g = find(&["G", "Y"]).unwrap_or(r);
+    let b = find(&["B", "Z"]).unwrap_or(r);
+
+    let pixels = (0..r.values.pixels().len())
+        .map(|i| {
+            Vector3::new(
+                r.values.pixels()[i],
+                g.values.pixels()[i],
+                b.values.pixels()[i],
+            )
+        })
+        .collect();
+
+    Some(Framebuffer::from_pixels(
+        r.values.width,
+        r.values.height,
+        pixels,
+    ))
+}
+
+// Read the linear color of an image file, the format is picked from its extension
+// 8 bit formats are taken to be sRGB encoded
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Framebuffer<Vector3>> {
+    let format = ImageFormat::from_path(&path);
+    let input = BufReader::new(File::open(path)?);
+
+    let decode = |image: Framebuffer<Vector3>| {
+        let srgb = Transfer::Srgb;
+        image.map(|p| Vector3::new(srgb.decode(p.x), srgb.decode(p.y), srgb.decode(p.z)))
+    };
+
+    match format {
+        Some(ImageFormat::Ppm) => read_ppm(input).map(decode),
+        Some(ImageFormat::Png) => read_png(input).map(decode),
+        Some(ImageFormat::Pfm) => read_pfm(input),
+        Some(ImageFormat::Hdr) => read_hdr(input),
+        Some(ImageFormat::Exr) => {
+            find_layer(&read_exr_channels(input)?, "").ok_or_else(|| invalid("no color channels"))
+        }
+        None => Err(io::Error::other("unknown image file extension")),
+    }
+}
+
+// Write an image to a file in the given format
+// Low dynamic range formats are tonemapped and quantized to 8 bits,
+// high dynamic range formats keep the linear radiance
+pub fn save<P: AsRef<Path>>(
+    path: P,
+    image: &Framebuffer<Vector3>,
+    format: ImageFormat,
+    tonemap: &Tonemap,
+) -> io::Result<()> {
+    let out = BufWriter::new(File::create(path)?);
+
+    match format {
+        ImageFormat::Ppm => write_ppm(out, &tonemap.apply_image(image).to_rgb8()),
+        ImageFormat::Png => write_png(out, &tonemap.apply_image(image).to_rgb8()),
+        ImageFormat::Pfm => write_pfm(out, image),
+        ImageFormat::Hdr => write_hdr(out, image),
+        ImageFormat::Exr => write_exr(out, image),
+    }
+}
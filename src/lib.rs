@@ -0,0 +1,59 @@
+// A path tracer after Peter Shirley's "Ray Tracing in One Weekend"
+//
+// Build a scene from primitives and materials, put it in a World or a BvhNode, point a
+// Camera at it and call render with RenderSettings to get a framebuffer of linear
+// radiance back. image::save writes it to disk, tonemapping it for 8 bit formats.
+// pipeline::render_image does all of that the way the command line does, with
+// checkpoints, adaptive sampling, AOVs and denoising.
+
+pub mod aabb;
+pub mod adaptive;
+pub mod animation;
+pub mod aov;
+pub mod bsdf;
+pub mod bvh;
+pub mod camera;
+pub mod checkpoint;
+pub mod compare;
+pub mod denoise;
+pub mod environment;
+pub mod filter;
+pub mod hit;
+pub mod image;
+pub mod light;
+pub mod material;
+pub mod mis;
+pub mod moving_sphere;
+pub mod pipeline;
+pub mod random;
+pub mod ray;
+pub mod render;
+pub mod sampler;
+pub mod scenes;
+pub mod sphere;
+pub mod stats;
+pub mod tonemap;
+pub mod vector;
+pub mod world;
+
+pub use crate::animation::{Animated, Animation, Keyframes};
+pub use crate::bsdf::{Bsdf, BsdfSample, Frame, Lobes};
+pub use crate::bvh::BvhNode;
+pub use crate::camera::Camera;
+pub use crate::compare::{compare, Comparison};
+pub use crate::environment::{Constant, Environment, EnvironmentMap, Gradient};
+pub use crate::filter::{Filter, FilterKind};
+pub use crate::hit::{Hit, HitRecord};
+pub use crate::image::{Framebuffer, ImageFormat};
+pub use crate::light::Lights;
+pub use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+pub use crate::mis::MisHeuristic;
+pub use crate::moving_sphere::MovingSphere;
+pub use crate::random::Rng;
+pub use crate::ray::Ray;
+pub use crate::render::{render, RenderSettings};
+pub use crate::sampler::{Sampler, SamplerKind};
+pub use crate::sphere::Sphere;
+pub use crate::tonemap::{Tonemap, Tonemapper, Transfer};
+pub use crate::vector::Vector3;
+pub use crate::world::World;
@@ -0,0 +1,57 @@
+use crate::environment::Environment;
+use crate::hit::Hit;
+use crate::sampler::Sampler;
+use crate::vector::Vector3;
+use std::sync::Arc;
+
+// Everything a path can find light from: the environment around the scene and the
+// objects in it that give off light, which are sampled directly at every bounce
+pub struct Lights {
+    pub environment: Box<dyn Environment>,
+    // Emissive objects, also part of the scene they were added to
+    pub emitters: Vec<Arc<dyn Hit>>,
+}
+
+impl Lights {
+    // Only the environment, with no emitters
+    pub fn new(environment: Box<dyn Environment>) -> Self {
+        Lights {
+            environment,
+            emitters: Vec::new(),
+        }
+    }
+
+    // Pick one emitter at random and a unit direction towards it from a point
+    // Returns the direction with its density over all the emitters, which may overlap
+    pub fn sample_emitter(
+        &self,
+        origin: Vector3,
+        time: f32,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Vector3, f32)> {
+        if self.emitters.is_empty() {
+            return None;
+        }
+
+        let count = self.emitters.len();
+        let index = ((sampler.get_1d() * count as f32) as usize).min(count - 1);
+        let (direction, _) = self.emitters[index].sample_direction(origin, time, sampler)?;
+
+        let pdf = self.emitter_pdf(origin, direction, time);
+        Some((direction, pdf)).filter(|&(_, pdf)| pdf > 0.0 && pdf.is_finite())
+    }
+
+    // Density of sample_emitter picking a unit direction
+    pub fn emitter_pdf(&self, origin: Vector3, direction: Vector3, time: f32) -> f32 {
+        if self.emitters.is_empty() {
+            return 0.0;
+        }
+
+        let sum: f32 = self
+            .emitters
+            .iter()
+            .map(|emitter| emitter.direction_pdf(origin, direction, time))
+            .sum();
+        sum / self.emitters.len() as f32
+    }
+}
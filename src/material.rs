@@ -1,7 +1,9 @@
+use crate::bsdf::{BsdfSample, Lobes};
 use crate::hit::HitRecord;
 use crate::ray::Ray;
+use crate::sampler::{cosine_hemisphere, in_unit_sphere, Sampler};
 use crate::vector::Vector3;
-use crate::{random_float, random_in_unit_sphere};
+use std::f32::consts::PI;
 
 pub fn schlick(cosine: f32, refractive_index: f32) -> f32 {
     let r0 = (1.0 - refractive_index) / (1.0 + refractive_index);
@@ -10,9 +12,60 @@ pub fn schlick(cosine: f32, refractive_index: f32) -> f32 {
     r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
 }
 
-pub trait Material {
-    // Return an optional scattered ray and attenuation
-    fn scatter(&self, ray_in: Ray, record: &HitRecord) -> Option<(Ray, Vector3)>;
+// Directions are unit vectors in the local shading frame of a hit, with the normal along
+// +z: wo points back along the ray, wi towards where light arrives from
+// bsdf::Bsdf puts a material at a hit and works in world space
+pub trait Material: Send + Sync {
+    // Kinds of scattering the material does
+    fn lobes(&self) -> Lobes;
+
+    // Pick a direction for the path to continue in, None where it is absorbed
+    fn sample(&self, wo: Vector3, sampler: &mut dyn Sampler) -> Option<BsdfSample>;
+
+    // The BSDF times the cosine: how much of the light arriving from wi the surface sends
+    // towards wo. Zero for delta lobes
+    fn eval(&self, _wo: Vector3, _wi: Vector3) -> Vector3 {
+        Vector3::default()
+    }
+
+    // Density per solid angle of sample picking wi, zero for delta lobes
+    fn pdf(&self, _wo: Vector3, _wi: Vector3) -> f32 {
+        0.0
+    }
+
+    // Surface color at a hit, written to the albedo AOV
+    fn albedo(&self, record: &HitRecord) -> Vector3;
+
+    // Radiance the surface gives off towards where the ray came from
+    fn emitted(&self, _ray_in: Ray, _record: &HitRecord) -> Vector3 {
+        Vector3::default()
+    }
+}
+
+// Mirror image of a local direction about the normal
+fn mirror(wo: Vector3) -> Vector3 {
+    Vector3::new(-wo.x, -wo.y, wo.z)
+}
+
+fn same_side(wo: Vector3, wi: Vector3) -> bool {
+    wo.z * wi.z > 0.0
+}
+
+// Density per solid angle of the direction to a point drawn uniformly from a ball, seen
+// from outside or from the surface of the ball
+fn ball_pdf(direction: Vector3, center: Vector3, radius: f32) -> f32 {
+    // Distances along the direction where it enters and leaves the ball
+    let b = direction.dot(center);
+    let discriminant = b * b - center.squared_length() + radius * radius;
+    if discriminant <= 0.0 {
+        return 0.0;
+    }
+
+    let root = discriminant.sqrt();
+    let (near, far) = ((b - root).max(0.0), (b + root).max(0.0));
+    let volume = 4.0 / 3.0 * PI * radius.powi(3);
+
+    (far.powi(3) - near.powi(3)) / (3.0 * volume)
 }
 
 pub struct Lambertian {
@@ -25,14 +78,46 @@ impl Lambertian {
     }
 }
 
+// Scatters the same light in every direction of the side it is seen from, picking them
+// by their cosine
 impl Material for Lambertian {
-    fn scatter(&self, ray_in: Ray, record: &HitRecord) -> Option<(Ray, Vector3)> {
-        let target = record.p + record.normal + random_in_unit_sphere();
+    fn lobes(&self) -> Lobes {
+        Lobes::DIFFUSE
+    }
+
+    fn sample(&self, wo: Vector3, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        let mut wi = cosine_hemisphere(sampler);
+        if wo.z < 0.0 {
+            wi.z = -wi.z;
+        }
+
+        let pdf = self.pdf(wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(BsdfSample {
+            direction: wi,
+            weight: self.albedo,
+            pdf,
+            lobe: Lobes::DIFFUSE,
+        })
+    }
 
-        Some((
-            Ray::with_time(record.p, target - record.p, ray_in.time),
-            self.albedo,
-        ))
+    fn eval(&self, wo: Vector3, wi: Vector3) -> Vector3 {
+        self.albedo * self.pdf(wo, wi)
+    }
+
+    fn pdf(&self, wo: Vector3, wi: Vector3) -> f32 {
+        if !same_side(wo, wi) {
+            return 0.0;
+        }
+
+        wi.z.abs() / PI
+    }
+
+    fn albedo(&self, _record: &HitRecord) -> Vector3 {
+        self.albedo
     }
 }
 
@@ -50,20 +135,59 @@ impl Metal {
     }
 }
 
+// A mirror, blurred by picking a uniform point of the fuzz ball around the mirror
+// direction. Directions below the surface are absorbed
 impl Material for Metal {
-    fn scatter(&self, ray_in: Ray, record: &HitRecord) -> Option<(Ray, Vector3)> {
-        let reflected = ray_in.direction.normalize().reflect(record.normal);
-        let scattered = Ray::with_time(
-            record.p,
-            reflected + random_in_unit_sphere() * self.fuzz,
-            ray_in.time,
-        );
-
-        return if scattered.direction.dot(record.normal) > 0.0 {
-            Some((scattered, self.albedo))
+    fn lobes(&self) -> Lobes {
+        if self.fuzz > 0.0 {
+            Lobes::GLOSSY
         } else {
-            None
-        };
+            Lobes::DELTA
+        }
+    }
+
+    fn sample(&self, wo: Vector3, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        let reflected = mirror(wo);
+        if self.fuzz <= 0.0 {
+            return Some(BsdfSample {
+                direction: reflected,
+                weight: self.albedo,
+                pdf: 1.0,
+                lobe: Lobes::DELTA,
+            });
+        }
+
+        let wi = (reflected + in_unit_sphere(sampler) * self.fuzz).normalize();
+        if !same_side(wo, wi) {
+            return None;
+        }
+
+        Some(BsdfSample {
+            direction: wi,
+            weight: self.albedo,
+            pdf: self.pdf(wo, wi),
+            lobe: Lobes::GLOSSY,
+        })
+    }
+
+    fn eval(&self, wo: Vector3, wi: Vector3) -> Vector3 {
+        if !same_side(wo, wi) {
+            return Vector3::default();
+        }
+
+        self.albedo * self.pdf(wo, wi)
+    }
+
+    fn pdf(&self, wo: Vector3, wi: Vector3) -> f32 {
+        if self.fuzz <= 0.0 {
+            return 0.0;
+        }
+
+        ball_pdf(wi, mirror(wo), self.fuzz)
+    }
+
+    fn albedo(&self, _record: &HitRecord) -> Vector3 {
+        self.albedo
     }
 }
 
@@ -77,42 +201,91 @@ impl Dielectric {
     }
 }
 
+// Reflects or refracts in proportion to Schlick's approximation of the Fresnel term
 impl Material for Dielectric {
-    fn scatter(&self, ray_in: Ray, record: &HitRecord) -> Option<(Ray, Vector3)> {
-        let reflected = ray_in.direction.normalize().reflect(record.normal);
-        let scattered;
-        let outward_normal;
-        let ni_over_nt;
-
-        let reflect_prob;
-        let cosine;
-
-        if ray_in.direction.dot(record.normal) > 0.0 {
-            outward_normal = -record.normal;
-            ni_over_nt = self.refractive_index;
-            cosine = ray_in.direction.dot(record.normal) / ray_in.direction.length()
-                * self.refractive_index;
+    fn lobes(&self) -> Lobes {
+        Lobes::DELTA
+    }
+
+    fn sample(&self, wo: Vector3, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        // Leaving the inside, the normal faces away from the ray
+        let (normal, ni_over_nt, cosine) = if wo.z < 0.0 {
+            let eta = self.refractive_index;
+            (-Vector3::unit_z(), eta, -wo.z * eta)
         } else {
-            outward_normal = record.normal;
-            ni_over_nt = 1.0 / self.refractive_index;
-            cosine = -ray_in.direction.dot(record.normal) / ray_in.direction.length();
-        }
+            (Vector3::unit_z(), 1.0 / self.refractive_index, wo.z)
+        };
 
-        let refracted = ray_in.direction.refract(outward_normal, ni_over_nt);
-        let attenuation = Vector3::new(1.0, 1.0, 1.0);
+        let refracted = (-wo).refract(normal, ni_over_nt);
+        let reflect_prob = if refracted != Vector3::default() {
+            schlick(cosine, self.refractive_index)
+        } else {
+            1.0
+        };
 
-        if refracted != Vector3::default() {
-            reflect_prob = schlick(cosine, self.refractive_index);
+        let (direction, pdf) = if sampler.get_1d() < reflect_prob {
+            (mirror(wo), reflect_prob)
         } else {
-            reflect_prob = 1.0;
+            (refracted.normalize(), 1.0 - reflect_prob)
+        };
+
+        Some(BsdfSample {
+            direction,
+            weight: Vector3::new(1.0, 1.0, 1.0),
+            pdf,
+            lobe: Lobes::DELTA,
+        })
+    }
+
+    fn albedo(&self, _record: &HitRecord) -> Vector3 {
+        // Clear glass
+        Vector3::new(1.0, 1.0, 1.0)
+    }
+}
+
+// A surface giving off the same light in every direction, and reflecting none
+// One-sided lights only shine on the side their normal points to
+pub struct DiffuseLight {
+    color: Vector3,
+    intensity: f32,
+    two_sided: bool,
+}
+
+impl DiffuseLight {
+    pub fn new(color: Vector3, intensity: f32) -> Self {
+        DiffuseLight {
+            color,
+            intensity,
+            two_sided: false,
         }
+    }
 
-        if random_float() < reflect_prob {
-            scattered = Ray::with_time(record.p, reflected, ray_in.time);
-        } else {
-            scattered = Ray::with_time(record.p, refracted, ray_in.time);
+    pub fn two_sided(color: Vector3, intensity: f32) -> Self {
+        DiffuseLight {
+            two_sided: true,
+            ..DiffuseLight::new(color, intensity)
         }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn lobes(&self) -> Lobes {
+        Lobes::NONE
+    }
+
+    fn sample(&self, _wo: Vector3, _sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        None
+    }
 
-        Some((scattered, attenuation))
+    fn albedo(&self, _record: &HitRecord) -> Vector3 {
+        self.color
+    }
+
+    fn emitted(&self, ray_in: Ray, record: &HitRecord) -> Vector3 {
+        if self.two_sided || ray_in.direction.dot(record.normal) < 0.0 {
+            self.color * self.intensity
+        } else {
+            Vector3::default()
+        }
     }
 }
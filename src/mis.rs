@@ -0,0 +1,68 @@
+use std::str::FromStr;
+
+// Weight of a sample taken with density `pdf` against another strategy that could have
+// taken it with density `other`, in proportion to the densities
+pub fn balance_heuristic(pdf: f32, other: f32) -> f32 {
+    if pdf <= 0.0 {
+        return 0.0;
+    }
+    if pdf.is_infinite() {
+        return 1.0;
+    }
+
+    pdf / (pdf + other)
+}
+
+// Weight of a sample taken with density `pdf` against another strategy that could have
+// taken it with density `other`, Veach's power heuristic with an exponent of 2
+pub fn power_heuristic(pdf: f32, other: f32) -> f32 {
+    if pdf <= 0.0 {
+        return 0.0;
+    }
+
+    let (a, b) = (pdf * pdf, other * other);
+    if a.is_infinite() {
+        return 1.0;
+    }
+
+    a / (a + b)
+}
+
+// How light found by both sampling the lights and scattering is shared between the two
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MisHeuristic {
+    Balance,
+    // Favours whichever strategy is much more likely, less noisy where one of them is
+    Power,
+}
+
+impl MisHeuristic {
+    pub const NAMES: &'static [&'static str] = &["balance", "power"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MisHeuristic::Balance => "balance",
+            MisHeuristic::Power => "power",
+        }
+    }
+
+    // Weight of a sample taken with density `pdf` against the other strategy
+    pub fn weight(self, pdf: f32, other: f32) -> f32 {
+        match self {
+            MisHeuristic::Balance => balance_heuristic(pdf, other),
+            MisHeuristic::Power => power_heuristic(pdf, other),
+        }
+    }
+}
+
+impl FromStr for MisHeuristic {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "balance" => Ok(MisHeuristic::Balance),
+            "power" => Ok(MisHeuristic::Power),
+            _ => Err(()),
+        }
+    }
+}
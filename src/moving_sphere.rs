@@ -1,9 +1,12 @@
-use crate::aabb::{Aabb, min, surrounding_box};
+use crate::aabb::{surrounding_box, Aabb};
 use crate::hit::{Hit, HitRecord};
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::sphere::{sample_sphere, sphere_pdf};
+use crate::stats::count_primitive_test;
 use crate::vector::Vector3;
-use std::rc::Rc;
+use std::sync::Arc;
 
 pub struct MovingSphere {
     center0: Vector3,
@@ -11,7 +14,7 @@ pub struct MovingSphere {
     radius: f32,
     time0: f32,
     time1: f32,
-    material: Rc<dyn Material>,
+    material: Arc<dyn Material>,
 }
 
 impl MovingSphere {
@@ -21,7 +24,7 @@ impl MovingSphere {
         time0: f32,
         time1: f32,
         radius: f32,
-        material: Rc<dyn Material>,
+        material: Arc<dyn Material>,
     ) -> Self {
         MovingSphere {
             center0,
@@ -41,6 +44,8 @@ impl MovingSphere {
 
 impl Hit for MovingSphere {
     fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        count_primitive_test();
+
         let result: Option<HitRecord> = None;
 
         let oc = ray.origin - self.center(ray.time);
@@ -83,4 +88,17 @@ impl Hit for MovingSphere {
 
         Some(surrounding_box(box0, box1))
     }
+
+    fn sample_direction(
+        &self,
+        origin: Vector3,
+        time: f32,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Vector3, f32)> {
+        sample_sphere(self.center(time), self.radius, origin, sampler)
+    }
+
+    fn direction_pdf(&self, origin: Vector3, direction: Vector3, time: f32) -> f32 {
+        sphere_pdf(self.center(time), self.radius, origin, direction)
+    }
 }
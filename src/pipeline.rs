@@ -0,0 +1,492 @@
+use crate::adaptive::{render_adaptive, sample_heatmap, AdaptiveSettings};
+use crate::animation::{frame_path, Animation};
+use crate::aov::{render_aovs, Aov};
+use crate::checkpoint::{self, Checkpoint};
+use crate::compare::{compare, false_color, Comparison};
+use crate::denoise::{denoise, DenoiseSettings, Guides};
+use crate::environment::MapFile;
+use crate::image::{self, find_layer, read_exr_channels, Framebuffer, ImageFormat};
+use crate::random::Rng;
+use crate::render::{render_pass, render_progressive, Accumulator, RenderSettings, Tile};
+use crate::scenes::{find_scene, Scene};
+use crate::stats::RenderStats;
+use crate::tonemap::Tonemap;
+use crate::vector::Vector3;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use std::{fs, io};
+
+// Region of the image to render
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Crop {
+    // Pixel coordinates, the end exclusive
+    Pixels([usize; 4]),
+    // Fractions of the image size
+    Window([f32; 4]),
+}
+
+impl Crop {
+    // The pixels covered for an image size, a window covers every pixel whose center it
+    // contains
+    pub fn region(self, width: usize, height: usize) -> Result<Tile, String> {
+        let region = match self {
+            Crop::Pixels([x0, y0, x1, y1]) => Tile { x0, y0, x1, y1 },
+            Crop::Window([x0, y0, x1, y1]) => {
+                let pixel = |t: f32, size: usize| (t * size as f32 - 0.5).ceil().max(0.0) as usize;
+                Tile {
+                    x0: pixel(x0, width),
+                    y0: pixel(y0, height),
+                    x1: pixel(x1, width),
+                    y1: pixel(y1, height),
+                }
+            }
+        };
+
+        if region.is_empty() || region.x1 > width || region.y1 > height {
+            return Err(format!(
+                "crop region {},{},{},{} is empty or outside the {}x{} image",
+                region.x0, region.y0, region.x1, region.y1, width, height
+            ));
+        }
+
+        Ok(region)
+    }
+}
+
+// What a render needs besides its RenderSettings: the scene, how its samples are taken
+// and where its progress is saved
+#[derive(Clone)]
+pub struct RenderOptions {
+    pub scene: &'static Scene,
+    // Replaces the crop of the settings once the image size is known
+    pub crop: Option<Crop>,
+    // Times the camera shutter opens and closes
+    pub shutter: (f32, f32),
+    // Environment map replacing the scene's own
+    pub environment: Option<MapFile>,
+    // Samples per pixel added by each progressive pass
+    pub pass_samples: Option<u32>,
+    // Display error below which adaptive sampling stops, see AdaptiveSettings, rendering
+    // in a single pass or progressively if there is none
+    pub adaptive: Option<f32>,
+    // Samples per pixel before adaptive sampling starts
+    pub min_samples: u32,
+    pub checkpoint: Option<String>,
+    // Time between previews and checkpoints
+    pub checkpoint_interval: Duration,
+    // Checkpoint to continue, restoring the settings its samples depend on
+    pub resume: Option<String>,
+}
+
+// Files a render is written to
+#[derive(Debug, Clone)]
+pub struct OutputOptions {
+    pub path: String,
+    pub format: ImageFormat,
+    pub tonemap: Tonemap,
+    // Write only the cropped region
+    pub crop_output: bool,
+    // AOVs written to files of their own
+    pub aovs: Vec<(Aov, String, ImageFormat)>,
+    // AOVs written as layers of the EXR output
+    pub aov_layers: Vec<Aov>,
+    pub denoise: bool,
+    // Image of the per-pixel sample counts
+    pub heatmap: Option<(String, ImageFormat)>,
+    // Write the render statistics as JSON next to the image
+    pub report: bool,
+}
+
+// Events of a running render
+#[derive(Debug)]
+pub enum Progress<'a> {
+    // A frame of a sequence starts
+    Frame(u32),
+    // A progressive pass finished, every rendered pixel has at least this many samples
+    Pass(u32),
+    // An adaptive pass finished, this many pixels took samples in it
+    AdaptivePass(usize),
+    // All samples are taken
+    Rendered(&'a RenderStats),
+}
+
+// Render a single image and write it with everything the output options ask for
+pub fn render_image<F>(
+    mut settings: RenderSettings,
+    mut options: RenderOptions,
+    output: &OutputOptions,
+    mut progress: F,
+) -> Result<RenderStats, String>
+where
+    F: FnMut(Progress),
+{
+    // Resuming restores everything the accumulated samples depend on
+    let (resumed, saved_crop) = match &options.resume {
+        Some(path) => {
+            let saved = checkpoint::load(path)
+                .map_err(|e| format!("cannot read checkpoint '{}': {}", path, e))?;
+
+            options.scene = find_scene(&saved.scene).ok_or_else(|| {
+                format!("checkpoint '{}' uses unknown scene '{}'", path, saved.scene)
+            })?;
+            settings.width = saved.accumulator.width();
+            settings.height = saved.accumulator.height();
+            settings.seed = saved.seed;
+            settings.max_depth = saved.max_depth;
+            settings.min_bounces = saved.min_bounces;
+            settings.sampler = saved.sampler;
+            settings.mis = saved.mis;
+            settings.filter = saved.filter;
+            options.shutter = saved.shutter;
+            options.environment = saved.environment;
+
+            (Some(saved.accumulator), Some(saved.crop))
+        }
+        None => (None, None),
+    };
+
+    let nx = settings.width;
+    let ny = settings.height;
+
+    // A crop in fractions depends on the image size, which a checkpoint may have changed
+    let crop = match options.crop {
+        Some(crop) => Some(crop.region(nx, ny)?),
+        None => None,
+    };
+
+    // The samples outside a resumed crop were never taken, it cannot change
+    settings.crop = match saved_crop {
+        Some(saved) if crop.is_some() && crop != saved => {
+            return Err(format!(
+                "the crop does not match the region of checkpoint '{}'",
+                options.resume.as_deref().unwrap_or_default()
+            ))
+        }
+        Some(saved) => saved,
+        None => crop,
+    };
+
+    // Scene, seeded so random scenes are reproducible
+    let build_start = Instant::now();
+    let (world, cam, mut lights) =
+        (options.scene.build)(nx, ny, options.shutter, &mut Rng::new(settings.seed));
+    if let Some(map) = &options.environment {
+        let map = map
+            .load()
+            .map_err(|e| format!("cannot read environment map '{}': {}", map.path, e))?;
+        lights.environment = Box::new(map);
+    }
+    let build_time = build_start.elapsed();
+
+    // Cropped outputs only keep the rendered region
+    let region = settings.region();
+    let output_crop = |pixels: &Framebuffer<Vector3>| {
+        if output.crop_output {
+            pixels.crop((region.x0, region.y0), region.width(), region.height())
+        } else {
+            pixels.clone()
+        }
+    };
+
+    // AOVs only follow camera rays to their first hit, render them up front
+    // The denoiser is guided by them
+    let aovs = if output.aovs.is_empty() && output.aov_layers.is_empty() && !output.denoise {
+        None
+    } else {
+        let aovs = render_aovs(world.as_ref(), &lights, &cam, &settings);
+        Some(if output.crop_output {
+            aovs.crop(region)
+        } else {
+            aovs
+        })
+    };
+
+    for (aov, path, format) in &output.aovs {
+        if let Some(aovs) = &aovs {
+            aovs.save(path, *aov, *format)
+                .map_err(|e| format!("cannot write '{}': {}", path, e))?;
+        }
+    }
+
+    let denoise_settings = DenoiseSettings {
+        threads: settings.threads,
+        ..DenoiseSettings::default()
+    };
+
+    // Write the image, HDR formats keep the linear radiance
+    let save_image = |pixels: &Framebuffer<Vector3>| {
+        let pixels = output_crop(pixels);
+        let pixels = match &aovs {
+            Some(aovs) if output.denoise => denoise(&pixels, &aovs.guides(), &denoise_settings),
+            _ => pixels,
+        };
+
+        match &aovs {
+            Some(aovs) if !output.aov_layers.is_empty() => {
+                aovs.save_layers(&output.path, &pixels, &output.aov_layers)
+            }
+            _ => image::save(&output.path, &pixels, output.format, &output.tonemap),
+        }
+        .map_err(|e| format!("cannot write '{}': {}", output.path, e))
+    };
+
+    // Checkpoints keep being written to the file that was resumed
+    let checkpoint_path = options
+        .checkpoint
+        .clone()
+        .or_else(|| options.resume.clone());
+    let save_checkpoint = |accumulator: &Accumulator| match &checkpoint_path {
+        Some(path) => {
+            let saved = Checkpoint {
+                scene: options.scene.name.to_string(),
+                seed: settings.seed,
+                max_depth: settings.max_depth,
+                min_bounces: settings.min_bounces,
+                filter: settings.filter,
+                shutter: options.shutter,
+                sampler: settings.sampler,
+                mis: settings.mis,
+                environment: options.environment.clone(),
+                crop: settings.crop,
+                accumulator: accumulator.clone(),
+            };
+
+            checkpoint::save(path, &saved)
+                .map_err(|e| format!("cannot write checkpoint '{}': {}", path, e))
+        }
+        None => Ok(()),
+    };
+
+    let render_start = Instant::now();
+
+    // Progressive rendering is implied by checkpoints
+    let pass_samples = options
+        .pass_samples
+        .or_else(|| checkpoint_path.as_ref().map(|_| 16));
+
+    let mut accumulator = resumed.unwrap_or_else(|| Accumulator::new(nx, ny));
+    let mut saved = Instant::now();
+
+    // Periodic preview and checkpoint
+    let mut on_pass = |accumulator: &Accumulator| -> Result<(), String> {
+        if saved.elapsed() >= options.checkpoint_interval {
+            save_image(&accumulator.resolve())?;
+            save_checkpoint(accumulator)?;
+            saved = Instant::now();
+        }
+
+        Ok(())
+    };
+
+    let counters = if let Some(threshold) = options.adaptive {
+        let adaptive = AdaptiveSettings {
+            threshold,
+            min_samples: options.min_samples.min(settings.samples as u32),
+            max_samples: settings.samples as u32,
+            pass_samples: pass_samples.unwrap_or(16),
+        };
+
+        render_adaptive(
+            world.as_ref(),
+            &lights,
+            &cam,
+            &settings,
+            &mut accumulator,
+            &adaptive,
+            |accumulator, active| {
+                progress(Progress::AdaptivePass(active));
+                on_pass(accumulator)
+            },
+        )?
+    } else if let Some(pass_samples) = pass_samples {
+        render_progressive(
+            world.as_ref(),
+            &lights,
+            &cam,
+            &settings,
+            &mut accumulator,
+            pass_samples,
+            |accumulator| {
+                progress(Progress::Pass(accumulator.min_samples_in(region)));
+                on_pass(accumulator)
+            },
+        )?
+    } else {
+        render_pass(
+            world.as_ref(),
+            &lights,
+            &cam,
+            &settings,
+            &mut accumulator,
+            settings.samples as u32,
+        )
+    };
+
+    let stats = RenderStats {
+        scene: options.scene.name.to_string(),
+        width: nx,
+        height: ny,
+        samples: settings.samples,
+        threads: settings.threads,
+        seed: settings.seed,
+        build_time,
+        render_time: render_start.elapsed(),
+        counters,
+    };
+
+    save_checkpoint(&accumulator)?;
+
+    progress(Progress::Rendered(&stats));
+
+    if output.report {
+        let path = Path::new(&output.path).with_extension("json");
+        fs::write(&path, stats.to_json())
+            .map_err(|e| format!("cannot write '{}': {}", path.display(), e))?;
+    }
+
+    if let Some((path, format)) = &output.heatmap {
+        let heatmap = output_crop(&sample_heatmap(&accumulator, settings.samples as u32));
+        image::save(path, &heatmap, *format, &Tonemap::identity())
+            .map_err(|e| format!("cannot write '{}': {}", path, e))?;
+    }
+
+    save_image(&accumulator.resolve())?;
+    Ok(stats)
+}
+
+// Render every frame of an animation into numbered files, the shutter of the options is
+// replaced by that of each frame
+pub fn render_sequence<F>(
+    settings: RenderSettings,
+    options: RenderOptions,
+    output: &OutputOptions,
+    animation: &Animation,
+    mut progress: F,
+) -> Result<(), String>
+where
+    F: FnMut(Progress),
+{
+    for frame in animation.frames() {
+        progress(Progress::Frame(frame));
+
+        let frame_options = RenderOptions {
+            shutter: animation.shutter(frame),
+            ..options.clone()
+        };
+        let frame_output = OutputOptions {
+            path: frame_path(&output.path, frame),
+            heatmap: output
+                .heatmap
+                .as_ref()
+                .map(|(path, format)| (frame_path(path, frame), *format)),
+            aovs: output
+                .aovs
+                .iter()
+                .map(|(aov, path, format)| (*aov, frame_path(path, frame), *format))
+                .collect(),
+            ..output.clone()
+        };
+        render_image(settings, frame_options, &frame_output, &mut progress)?;
+    }
+
+    Ok(())
+}
+
+pub struct DenoiseOptions {
+    pub input: String,
+    pub albedo: Option<String>,
+    pub normal: Option<String>,
+    pub depth: Option<String>,
+    pub output: String,
+    pub format: ImageFormat,
+    pub settings: DenoiseSettings,
+}
+
+// Denoise a saved render, with guides from its EXR layers or from separate files
+pub fn denoise_file(options: &DenoiseOptions) -> Result<(), String> {
+    let read_error = |path: &str, e| format!("cannot read '{}': {}", path, e);
+    let load = |path: &Option<String>| match path {
+        Some(path) => image::load(path).map(Some).map_err(|e| read_error(path, e)),
+        None => Ok(None),
+    };
+
+    let mut albedo = load(&options.albedo)?;
+    let mut normal = load(&options.normal)?;
+    let mut depth = load(&options.depth)?.map(|depth| depth.map(|d| d.x));
+
+    let input = &options.input;
+    let radiance = if ImageFormat::from_path(input) == Some(ImageFormat::Exr) {
+        let file = fs::File::open(input).map_err(|e| read_error(input, e))?;
+        let channels =
+            read_exr_channels(io::BufReader::new(file)).map_err(|e| read_error(input, e))?;
+
+        albedo = albedo.or_else(|| find_layer(&channels, "albedo"));
+        normal = normal.or_else(|| find_layer(&channels, "normal"));
+        depth = depth.or_else(|| find_layer(&channels, "depth").map(|depth| depth.map(|d| d.x)));
+
+        find_layer(&channels, "").ok_or_else(|| format!("'{}' has no color channels", input))?
+    } else {
+        image::load(input).map_err(|e| read_error(input, e))?
+    };
+
+    let size = (radiance.width(), radiance.height());
+    let mismatch = albedo.iter().any(|a| (a.width(), a.height()) != size)
+        || normal.iter().any(|n| (n.width(), n.height()) != size)
+        || depth.iter().any(|d| (d.width(), d.height()) != size);
+    if mismatch {
+        return Err("the guide images do not match the size of the input".to_string());
+    }
+
+    let guides = Guides {
+        albedo: albedo.as_ref(),
+        normal: normal.as_ref(),
+        depth: depth.as_ref(),
+    };
+
+    let denoised = denoise(&radiance, &guides, &options.settings);
+    image::save(
+        &options.output,
+        &denoised,
+        options.format,
+        &Tonemap::default(),
+    )
+    .map_err(|e| format!("cannot write '{}': {}", options.output, e))
+}
+
+pub struct CompareOptions {
+    pub image: String,
+    pub reference: String,
+    // False color FLIP error image
+    pub output: String,
+    pub format: ImageFormat,
+    pub pixels_per_degree: f32,
+}
+
+// Measure the error of a render against a reference and write the FLIP error image
+pub fn compare_files(options: &CompareOptions) -> Result<Comparison, String> {
+    let load = |path: &str| image::load(path).map_err(|e| format!("cannot read '{}': {}", path, e));
+    let image = load(&options.image)?;
+    let reference = load(&options.reference)?;
+
+    if (image.width(), image.height()) != (reference.width(), reference.height()) {
+        return Err(format!(
+            "'{}' is {}x{} but the reference is {}x{}",
+            options.image,
+            image.width(),
+            image.height(),
+            reference.width(),
+            reference.height()
+        ));
+    }
+
+    let comparison = compare(&image, &reference, options.pixels_per_degree);
+    image::save(
+        &options.output,
+        &false_color(&comparison.flip_map),
+        options.format,
+        &Tonemap::identity(),
+    )
+    .map_err(|e| format!("cannot write '{}': {}", options.output, e))?;
+
+    Ok(comparison)
+}
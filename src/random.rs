@@ -0,0 +1,77 @@
+use crate::vector::Vector3;
+
+// Scramble the bits of a 64 bit value (SplitMix64 finalizer)
+pub fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// Small seedable generator (PCG32), passed explicitly to everything that samples
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rng {
+    state: u64,
+    increment: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Rng {
+            state: 0,
+            increment: (mix(seed ^ 0x5851_f42d_4c95_7f2d) << 1) | 1,
+        };
+
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(mix(seed));
+        rng.next_u32();
+
+        rng
+    }
+
+    // Generator for one sample of one pixel
+    // Depends only on its inputs, not on the thread or the order samples are taken in
+    pub fn for_sample(seed: u64, pixel: u64, sample: u32) -> Self {
+        Rng::new(mix(mix(seed) ^ pixel) ^ sample as u64)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(self.increment);
+
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+
+        xorshifted.rotate_right(rot)
+    }
+
+    // Generate a random float in [0, 1)
+    pub fn float(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    // Generate a random point in 3D space, discard if outside of the unit sphere
+    pub fn in_unit_sphere(&mut self) -> Vector3 {
+        loop {
+            let p = Vector3::new(self.float(), self.float(), self.float()) * 2.0
+                - Vector3::new(1.0, 1.0, 1.0);
+
+            if p.squared_length() < 1.0 {
+                return p;
+            }
+        }
+    }
+
+    // Generate a random point in 2D space, discard if outside of the unit circle
+    pub fn in_unit_disk(&mut self) -> Vector3 {
+        loop {
+            let p =
+                Vector3::new(self.float(), self.float(), 0.0) * 2.0 - Vector3::new(1.0, 1.0, 0.0);
+
+            if p.dot(p) < 1.0 {
+                return p;
+            }
+        }
+    }
+}
@@ -0,0 +1,783 @@
+use crate::bsdf::{Bsdf, Lobes};
+use crate::camera::Camera;
+use crate::filter::Filter;
+use crate::hit::{Hit, HitRecord};
+use crate::image::{luminance, Framebuffer};
+use crate::light::Lights;
+use crate::mis::MisHeuristic;
+use crate::ray::Ray;
+use crate::sampler::{Sampler, SamplerKind};
+use crate::stats::{count_ray, count_shadow_ray, take_counters, Counters};
+use crate::vector::Vector3;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+#[derive(Debug, Copy, Clone)]
+pub struct RenderSettings {
+    pub width: usize,
+    pub height: usize,
+    pub samples: usize,
+    pub max_depth: usize,
+    pub min_bounces: usize,
+    pub seed: u64,
+    pub threads: usize,
+    pub tile_size: usize,
+    pub filter: Filter,
+    // Only pixels inside the crop window are rendered, the whole image if there is none
+    pub crop: Option<Tile>,
+    pub tile_order: TileOrder,
+    pub sampler: SamplerKind,
+    // Weighting of light found both by sampling it directly and by scattering into it
+    pub mis: MisHeuristic,
+}
+
+impl RenderSettings {
+    pub fn new(width: usize, height: usize, samples: usize) -> Self {
+        RenderSettings {
+            width,
+            height,
+            samples,
+            max_depth: 50,
+            min_bounces: 3,
+            seed: 0,
+            threads: available_threads(),
+            tile_size: 16,
+            filter: Filter::default(),
+            crop: None,
+            tile_order: TileOrder::Scanline,
+            sampler: SamplerKind::Independent,
+            mis: MisHeuristic::Power,
+        }
+    }
+
+    // The part of the image that gets rendered
+    pub fn region(&self) -> Tile {
+        let full = Tile::full(self.width, self.height);
+        self.crop.map_or(full, |crop| crop.intersect(full))
+    }
+}
+
+// Number of worker threads to use when none is requested explicitly
+pub fn available_threads() -> usize {
+    thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+// A rectangular block of pixels, in image coordinates (top to bottom)
+// x1 and y1 are one past the last column and row
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Tile {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+}
+
+impl Tile {
+    // The whole image
+    pub fn full(width: usize, height: usize) -> Self {
+        Tile {
+            x0: 0,
+            y0: 0,
+            x1: width,
+            y1: height,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.x1.saturating_sub(self.x0)
+    }
+
+    pub fn height(&self) -> usize {
+        self.y1.saturating_sub(self.y0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width() == 0 || self.height() == 0
+    }
+
+    // The part of this tile that is also inside another
+    pub fn intersect(&self, other: Tile) -> Tile {
+        Tile {
+            x0: self.x0.max(other.x0),
+            y0: self.y0.max(other.y0),
+            x1: self.x1.min(other.x1),
+            y1: self.y1.min(other.y1),
+        }
+    }
+}
+
+// Order tiles are handed to the workers in, which is the order a preview fills in
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TileOrder {
+    // Left to right, top to bottom
+    Scanline,
+    // Outwards from the center of the region, where the subject usually is
+    Spiral,
+    // Along a Hilbert curve, keeping consecutive tiles close together
+    Hilbert,
+}
+
+impl FromStr for TileOrder {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "scanline" => Ok(TileOrder::Scanline),
+            "spiral" => Ok(TileOrder::Spiral),
+            "hilbert" => Ok(TileOrder::Hilbert),
+            _ => Err(()),
+        }
+    }
+}
+
+// Cells of a columns x rows grid in a square spiral from the center
+fn spiral(columns: usize, rows: usize) -> Vec<(usize, usize)> {
+    let total = columns * rows;
+    let mut cells = Vec::with_capacity(total);
+
+    let (mut x, mut y) = (((columns - 1) / 2) as isize, ((rows - 1) / 2) as isize);
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut length = 1;
+    let mut turn = 0;
+
+    cells.push((x as usize, y as usize));
+    while cells.len() < total {
+        // Runs grow by one every second turn: 1, 1, 2, 2, 3, 3, ...
+        for _ in 0..2 {
+            let (dx, dy) = directions[turn % 4];
+            for _ in 0..length {
+                x += dx;
+                y += dy;
+                if x >= 0 && y >= 0 && (x as usize) < columns && (y as usize) < rows {
+                    cells.push((x as usize, y as usize));
+                }
+            }
+            turn += 1;
+        }
+        length += 1;
+    }
+
+    cells
+}
+
+// Position of step d along a Hilbert curve filling an n x n grid, n a power of two
+fn hilbert_point(n: usize, mut d: usize) -> (usize, usize) {
+    let (mut x, mut y) = (0, 0);
+    let mut s = 1;
+
+    while s < n {
+        let rx = 1 & (d / 2);
+        let ry = 1 & (d ^ rx);
+
+        // Rotate the quadrant
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+
+        x += s * rx;
+        y += s * ry;
+        d /= 4;
+        s *= 2;
+    }
+
+    (x, y)
+}
+
+// Cells of a columns x rows grid along a Hilbert curve
+fn hilbert(columns: usize, rows: usize) -> Vec<(usize, usize)> {
+    let n = columns.max(rows).next_power_of_two();
+
+    (0..n * n)
+        .map(|d| hilbert_point(n, d))
+        .filter(|&(x, y)| x < columns && y < rows)
+        .collect()
+}
+
+// Split a region of the image into tiles, in the given order
+pub fn tiles(region: Tile, tile_size: usize, order: TileOrder) -> Vec<Tile> {
+    if region.is_empty() {
+        return Vec::new();
+    }
+
+    let size = tile_size.max(1);
+    let columns = region.width().div_ceil(size);
+    let rows = region.height().div_ceil(size);
+
+    let cells = match order {
+        TileOrder::Scanline => (0..rows)
+            .flat_map(|y| (0..columns).map(move |x| (x, y)))
+            .collect(),
+        TileOrder::Spiral => spiral(columns, rows),
+        TileOrder::Hilbert => hilbert(columns, rows),
+    };
+
+    cells
+        .into_iter()
+        .map(|(x, y)| {
+            let x0 = region.x0 + x * size;
+            let y0 = region.y0 + y * size;
+            Tile {
+                x0,
+                y0,
+                x1: (x0 + size).min(region.x1),
+                y1: (y0 + size).min(region.y1),
+            }
+        })
+        .collect()
+}
+
+// Light reaching a hit straight from the environment, along a direction sampled towards
+// its bright parts and weighted against scattering finding the same light
+fn direct_environment(
+    ray: Ray,
+    record: &HitRecord,
+    bsdf: &Bsdf,
+    world: &dyn Hit,
+    lights: &Lights,
+    mis: MisHeuristic,
+    sampler: &mut dyn Sampler,
+) -> Vector3 {
+    let (direction, radiance, pdf) = match lights.environment.sample(sampler) {
+        Some(sample) if sample.2 > 0.0 => sample,
+        _ => return Vector3::default(),
+    };
+
+    let weight = bsdf.eval(direction);
+    if weight == Vector3::default() {
+        return Vector3::default();
+    }
+    let scatter_pdf = bsdf.pdf(direction);
+
+    count_shadow_ray();
+    let shadow = Ray::with_time(record.p, direction, ray.time);
+    if world.hit(shadow, 0.0001, f32::MAX).is_some() {
+        return Vector3::default();
+    }
+
+    weight * radiance * (mis.weight(pdf, scatter_pdf) / pdf)
+}
+
+// Light reaching a hit straight from an emitter, along a direction sampled towards one
+// and weighted against scattering finding the same light
+// The shadow ray counts whatever it meets first, which is dark unless it glows
+fn direct_emitters(
+    ray: Ray,
+    record: &HitRecord,
+    bsdf: &Bsdf,
+    world: &dyn Hit,
+    lights: &Lights,
+    mis: MisHeuristic,
+    sampler: &mut dyn Sampler,
+) -> Vector3 {
+    let (direction, pdf) = match lights.sample_emitter(record.p, ray.time, sampler) {
+        Some(sample) => sample,
+        None => return Vector3::default(),
+    };
+
+    let weight = bsdf.eval(direction);
+    if weight == Vector3::default() {
+        return Vector3::default();
+    }
+    let scatter_pdf = bsdf.pdf(direction);
+
+    count_shadow_ray();
+    let shadow = Ray::with_time(record.p, direction, ray.time);
+    let emitted = match world.hit(shadow, 0.0001, f32::MAX) {
+        Some(hit) => hit.material.emitted(shadow, &hit),
+        None => return Vector3::default(),
+    };
+
+    weight * emitted * (mis.weight(pdf, scatter_pdf) / pdf)
+}
+
+// Compute the final color by following the path bounce by bounce, adding the light
+// given off by every surface it meets
+// At every bounce the environment and the emitters are also sampled directly, where they
+// can be, and light found both ways is weighted by multiple importance sampling
+// After min_bounces, Russian roulette ends dim paths early and boosts the survivors,
+// which keeps the estimate unbiased. Paths reaching max_depth are cut off as black.
+pub fn color(
+    mut ray: Ray,
+    world: &dyn Hit,
+    lights: &Lights,
+    max_depth: usize,
+    min_bounces: usize,
+    mis: MisHeuristic,
+    sampler: &mut dyn Sampler,
+) -> Vector3 {
+    let mut radiance = Vector3::default();
+    let mut throughput = Vector3::new(1.0, 1.0, 1.0);
+
+    // Density the last bounce picked the ray's direction with, if the lights could have
+    // been sampled along it as well
+    let mut scatter_pdf = None;
+
+    for depth in 0..max_depth {
+        count_ray(depth == 0);
+
+        let record = match world.hit(ray, 0.0001, f32::MAX) {
+            Some(record) => record,
+            None => {
+                let direction = ray.direction.normalize();
+                let weight = match scatter_pdf {
+                    Some(pdf) => mis.weight(pdf, lights.environment.pdf(direction)),
+                    None => 1.0,
+                };
+                return radiance + throughput * lights.environment.radiance(direction) * weight;
+            }
+        };
+
+        let emitted = record.material.emitted(ray, &record);
+        if emitted != Vector3::default() {
+            let weight = match scatter_pdf {
+                Some(pdf) => {
+                    let direction = ray.direction.normalize();
+                    mis.weight(pdf, lights.emitter_pdf(ray.origin, direction, ray.time))
+                }
+                None => 1.0,
+            };
+            radiance += throughput * emitted * weight;
+        }
+
+        // Exact directions never meet a light picked at random
+        let bsdf = Bsdf::new(ray, &record);
+        if !bsdf.lobes().is_delta() {
+            radiance +=
+                throughput * direct_environment(ray, &record, &bsdf, world, lights, mis, sampler);
+            radiance +=
+                throughput * direct_emitters(ray, &record, &bsdf, world, lights, mis, sampler);
+        }
+
+        let sample = match bsdf.sample(sampler) {
+            Some(sample) => sample,
+            None => return radiance,
+        };
+
+        scatter_pdf = Some(sample.pdf).filter(|_| !sample.lobe.contains(Lobes::DELTA));
+        throughput *= sample.weight;
+        ray = Ray::with_time(record.p, sample.direction, ray.time);
+
+        if depth + 1 >= min_bounces {
+            // Survive with a probability following the path throughput
+            let survival = throughput.x.max(throughput.y).max(throughput.z).min(0.95);
+            if sampler.get_1d() >= survival {
+                return radiance;
+            }
+
+            throughput /= survival;
+        }
+    }
+
+    radiance
+}
+
+// Running per-pixel sums of radiance samples and how many were taken
+// Samples are weighted into the pixels around them by the reconstruction filter, while
+// the luminance sums only count a pixel's own samples and give its variance
+#[derive(Debug, Clone, PartialEq)]
+pub struct Accumulator {
+    pub sum: Framebuffer<Vector3>,
+    pub weights: Framebuffer<f32>,
+    // Sums of the absolute filter weights, telling weight sums that negative lobes
+    // cancelled out from ones that are small
+    pub abs_weights: Framebuffer<f32>,
+    pub luminance: Framebuffer<f32>,
+    pub sum_squares: Framebuffer<f32>,
+    pub samples: Framebuffer<u32>,
+}
+
+impl Accumulator {
+    pub fn new(width: usize, height: usize) -> Self {
+        Accumulator {
+            sum: Framebuffer::new(width, height),
+            weights: Framebuffer::new(width, height),
+            abs_weights: Framebuffer::new(width, height),
+            luminance: Framebuffer::new(width, height),
+            sum_squares: Framebuffer::new(width, height),
+            samples: Framebuffer::new(width, height),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.sum.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.sum.height()
+    }
+
+    // Fewest samples taken by any pixel
+    pub fn min_samples(&self) -> u32 {
+        self.min_samples_in(Tile::full(self.width(), self.height()))
+    }
+
+    // Fewest samples taken by any pixel of a region
+    pub fn min_samples_in(&self, region: Tile) -> u32 {
+        (region.y0..region.y1)
+            .flat_map(|y| self.samples.row(y)[region.x0..region.x1].iter().copied())
+            .min()
+            .unwrap_or(0)
+    }
+
+    // Filtered radiance of every pixel, never negative
+    // With negative lobes the weights of a pixel can nearly cancel out, so they are
+    // taken as at least a quarter of their absolute sum instead of blowing up the radiance
+    pub fn resolve(&self) -> Framebuffer<Vector3> {
+        let mut image = Framebuffer::new(self.width(), self.height());
+
+        for (i, p) in image.pixels_mut().iter_mut().enumerate() {
+            let weight = self.weights.pixels()[i].max(0.25 * self.abs_weights.pixels()[i]);
+            if weight > 0.0 {
+                let radiance = self.sum.pixels()[i] / weight;
+                *p = Vector3::new(
+                    radiance.x.max(0.0),
+                    radiance.y.max(0.0),
+                    radiance.z.max(0.0),
+                );
+            }
+        }
+
+        image
+    }
+
+    // Unbiased variance of a pixel's luminance samples
+    pub fn variance(&self, x: usize, y: usize) -> f32 {
+        let n = self.samples.get(x, y) as f32;
+        if n < 2.0 {
+            return f32::INFINITY;
+        }
+
+        let mean = self.luminance.get(x, y) / n;
+        let mean_squares = self.sum_squares.get(x, y) / n;
+
+        ((mean_squares - mean * mean) * n / (n - 1.0)).max(0.0)
+    }
+}
+
+// Radiance splatted by the samples of one tile, over the tile grown by the filter radius
+// Nothing is splatted outside the rendered region, so a crop window keeps hard edges
+struct Splats {
+    region: Tile,
+    sum: Framebuffer<Vector3>,
+    weights: Framebuffer<f32>,
+    abs_weights: Framebuffer<f32>,
+}
+
+impl Splats {
+    fn new(tile: Tile, settings: &RenderSettings) -> Self {
+        let margin = (settings.filter.radius + 0.5).ceil() as usize;
+        let region = Tile {
+            x0: tile.x0.saturating_sub(margin),
+            y0: tile.y0.saturating_sub(margin),
+            x1: tile.x1 + margin,
+            y1: tile.y1 + margin,
+        }
+        .intersect(settings.region());
+
+        let (width, height) = (region.x1 - region.x0, region.y1 - region.y0);
+        Splats {
+            region,
+            sum: Framebuffer::new(width, height),
+            weights: Framebuffer::new(width, height),
+            abs_weights: Framebuffer::new(width, height),
+        }
+    }
+
+    // Weight a sample at a point of the film into every pixel whose center is within
+    // the filter radius
+    fn add(&mut self, filter: &Filter, (fx, fy): (f32, f32), col: Vector3) {
+        let region = self.region;
+        let range = |f: f32, low: usize, high: usize| {
+            let first = (f - 0.5 - filter.radius).ceil().max(low as f32) as usize;
+            let last = ((f - 0.5 + filter.radius).floor() + 1.0).clamp(0.0, high as f32);
+            first..last as usize
+        };
+
+        for y in range(fy, region.y0, region.y1) {
+            for x in range(fx, region.x0, region.x1) {
+                let weight = filter.weight(x as f32 + 0.5 - fx, y as f32 + 0.5 - fy);
+                if weight == 0.0 {
+                    continue;
+                }
+
+                let (lx, ly) = (x - region.x0, y - region.y0);
+                self.sum.set(lx, ly, self.sum.get(lx, ly) + col * weight);
+                self.weights.set(lx, ly, self.weights.get(lx, ly) + weight);
+                let abs_weight = self.abs_weights.get(lx, ly) + weight.abs();
+                self.abs_weights.set(lx, ly, abs_weight);
+            }
+        }
+    }
+}
+
+// Random point inside pixel (x, y), in pixel units from the top left corner of the film
+pub fn jitter((x, y): (usize, usize), sampler: &mut dyn Sampler) -> (f32, f32) {
+    let (u, v) = sampler.get_2d();
+    (x as f32 + u, y as f32 + v)
+}
+
+// Camera ray through a point of the film
+pub fn film_ray(
+    camera: &Camera,
+    settings: &RenderSettings,
+    (fx, fy): (f32, f32),
+    sampler: &mut dyn Sampler,
+) -> Ray {
+    // Film rows go top to bottom, the camera's v axis goes bottom to top
+    let u = fx / settings.width as f32;
+    let v = 1.0 - fy / settings.height as f32;
+
+    camera.ray(u, v, sampler)
+}
+
+// Camera ray through a random point of pixel (x, y)
+pub fn pixel_ray(
+    camera: &Camera,
+    settings: &RenderSettings,
+    pixel: (usize, usize),
+    sampler: &mut dyn Sampler,
+) -> Ray {
+    let film = jitter(pixel, sampler);
+    film_ray(camera, settings, film, sampler)
+}
+
+// Take `count` more samples of a single pixel, splatting their radiance
+// Returns the pixel's updated luminance sums and sample count, summed in sample order so
+// they do not depend on how passes are split
+#[allow(clippy::too_many_arguments)]
+fn render_pixel(
+    world: &dyn Hit,
+    lights: &Lights,
+    camera: &Camera,
+    settings: &RenderSettings,
+    accumulator: &Accumulator,
+    (x, y): (usize, usize),
+    count: u32,
+    sampler: &mut dyn Sampler,
+    splats: &mut Splats,
+) -> (f32, f32, u32) {
+    let mut sum = accumulator.luminance.get(x, y);
+    let mut squares = accumulator.sum_squares.get(x, y);
+    let first = accumulator.samples.get(x, y);
+
+    for sample in first..first + count {
+        sampler.start_sample((x, y), sample);
+        let film = jitter((x, y), sampler);
+        let ray = film_ray(camera, settings, film, sampler);
+
+        // Compute color
+        let c = color(
+            ray,
+            world,
+            lights,
+            settings.max_depth,
+            settings.min_bounces,
+            settings.mis,
+            sampler,
+        );
+        splats.add(&settings.filter, film, c);
+
+        let l = luminance(c);
+        sum += l;
+        squares += l * l;
+    }
+
+    (sum, squares, first + count)
+}
+
+// Render one tile, returning its splats and the statistics of its pixels row by row
+fn render_tile(
+    world: &dyn Hit,
+    lights: &Lights,
+    camera: &Camera,
+    settings: &RenderSettings,
+    accumulator: &Accumulator,
+    budget: &Framebuffer<u32>,
+    tile: Tile,
+) -> (Splats, Vec<(f32, f32, u32)>) {
+    let mut splats = Splats::new(tile, settings);
+    let mut sampler = settings.sampler.create(settings.seed, settings.samples);
+    let mut pixels = Vec::with_capacity((tile.x1 - tile.x0) * (tile.y1 - tile.y0));
+
+    for y in tile.y0..tile.y1 {
+        for x in tile.x0..tile.x1 {
+            let count = budget.get(x, y);
+            pixels.push(render_pixel(
+                world,
+                lights,
+                camera,
+                settings,
+                accumulator,
+                (x, y),
+                count,
+                sampler.as_mut(),
+                &mut splats,
+            ));
+        }
+    }
+
+    (splats, pixels)
+}
+
+// Run `render` on every tile of the image, on a pool of worker threads pulling tiles
+// from a shared queue
+// Returns the results in tile order, and the work counters of all workers
+pub fn render_tiles<R, F>(settings: &RenderSettings, render: F) -> (Vec<(Tile, R)>, Counters)
+where
+    R: Send,
+    F: Fn(Tile) -> R + Sync,
+{
+    let tiles = tiles(settings.region(), settings.tile_size, settings.tile_order);
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(tiles.len()));
+    let counters = Mutex::new(Counters::default());
+
+    thread::scope(|s| {
+        for _ in 0..settings.threads.max(1) {
+            s.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    if index >= tiles.len() {
+                        break;
+                    }
+
+                    let tile = tiles[index];
+                    let result = render(tile);
+                    results.lock().unwrap().push((index, tile, result));
+                }
+
+                *counters.lock().unwrap() += take_counters();
+            });
+        }
+    });
+
+    // Merging in a fixed order keeps overlapping splats independent of the thread count
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _, _)| *index);
+
+    (
+        results
+            .into_iter()
+            .map(|(_, tile, result)| (tile, result))
+            .collect(),
+        counters.into_inner().unwrap(),
+    )
+}
+
+// Add budget[x, y] more samples to every pixel of the accumulator
+// Returns the work counters of all workers
+pub fn render_samples(
+    world: &dyn Hit,
+    lights: &Lights,
+    camera: &Camera,
+    settings: &RenderSettings,
+    accumulator: &mut Accumulator,
+    budget: &Framebuffer<u32>,
+) -> Counters {
+    // Workers only read the accumulator, finished tiles are merged afterwards
+    let previous: &Accumulator = accumulator;
+    let (results, counters) = render_tiles(settings, |tile| {
+        render_tile(world, lights, camera, settings, previous, budget, tile)
+    });
+
+    for (tile, (splats, pixels)) in results {
+        let region = splats.region;
+        for y in region.y0..region.y1 {
+            for x in region.x0..region.x1 {
+                let (lx, ly) = (x - region.x0, y - region.y0);
+                let sum = accumulator.sum.get(x, y) + splats.sum.get(lx, ly);
+                let weight = accumulator.weights.get(x, y) + splats.weights.get(lx, ly);
+                let abs_weight = accumulator.abs_weights.get(x, y) + splats.abs_weights.get(lx, ly);
+                accumulator.sum.set(x, y, sum);
+                accumulator.weights.set(x, y, weight);
+                accumulator.abs_weights.set(x, y, abs_weight);
+            }
+        }
+
+        let mut pixels = pixels.into_iter();
+        for y in tile.y0..tile.y1 {
+            for x in tile.x0..tile.x1 {
+                let (sum, squares, n) = pixels.next().unwrap();
+                accumulator.luminance.set(x, y, sum);
+                accumulator.sum_squares.set(x, y, squares);
+                accumulator.samples.set(x, y, n);
+            }
+        }
+    }
+
+    counters
+}
+
+// Add `samples` more samples to every pixel of the accumulator
+pub fn render_pass(
+    world: &dyn Hit,
+    lights: &Lights,
+    camera: &Camera,
+    settings: &RenderSettings,
+    accumulator: &mut Accumulator,
+    samples: u32,
+) -> Counters {
+    let mut budget = Framebuffer::new(settings.width, settings.height);
+    budget.pixels_mut().iter_mut().for_each(|n| *n = samples);
+
+    render_samples(world, lights, camera, settings, accumulator, &budget)
+}
+
+// Render the scene in one pass of settings.samples samples per pixel
+// Returns linear radiance
+pub fn render(
+    world: &dyn Hit,
+    lights: &Lights,
+    camera: &Camera,
+    settings: &RenderSettings,
+) -> Framebuffer<Vector3> {
+    let mut accumulator = Accumulator::new(settings.width, settings.height);
+    render_pass(
+        world,
+        lights,
+        camera,
+        settings,
+        &mut accumulator,
+        settings.samples as u32,
+    );
+
+    accumulator.resolve()
+}
+
+// Keep rendering passes of pass_samples samples per pixel until every rendered pixel has
+// settings.samples samples, calling on_pass after each pass
+pub fn render_progressive<F, E>(
+    world: &dyn Hit,
+    lights: &Lights,
+    camera: &Camera,
+    settings: &RenderSettings,
+    accumulator: &mut Accumulator,
+    pass_samples: u32,
+    mut on_pass: F,
+) -> Result<Counters, E>
+where
+    F: FnMut(&Accumulator) -> Result<(), E>,
+{
+    let target = settings.samples as u32;
+    let mut counters = Counters::default();
+
+    loop {
+        let taken = accumulator.min_samples_in(settings.region());
+        if taken >= target {
+            return Ok(counters);
+        }
+
+        let samples = pass_samples.max(1).min(target - taken);
+        counters += render_pass(world, lights, camera, settings, accumulator, samples);
+
+        on_pass(accumulator)?;
+    }
+}
@@ -0,0 +1,529 @@
+use crate::random::{mix, Rng};
+use crate::vector::Vector3;
+use std::f32::consts::PI;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+// Source of the random numbers of a camera path, handed out one dimension at a time
+// Every sample starts over at the first dimension and uses them in the same order: the
+// film position, the lens, the time, then those of each bounce
+pub trait Sampler {
+    // Begin sample `index` of pixel (x, y)
+    fn start_sample(&mut self, pixel: (usize, usize), index: u32);
+
+    // Next dimension, in [0, 1)
+    fn get_1d(&mut self) -> f32;
+
+    // Next two dimensions, distributed well together
+    fn get_2d(&mut self) -> (f32, f32);
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SamplerKind {
+    // Uncorrelated random numbers for every dimension
+    Independent,
+    // One jittered sample per stratum of the unit interval or square, in random order
+    Stratified,
+    // Halton sequence, its digits Owen scrambled per pixel
+    Halton,
+    // Sobol points with hash-based Owen scrambling, shuffled per pixel
+    Sobol,
+    // Sobol points shared by every pixel, rotated by a blue noise mask so neighbouring
+    // pixels make different errors
+    BlueNoise,
+}
+
+impl SamplerKind {
+    pub const NAMES: &'static [&'static str] =
+        &["independent", "stratified", "halton", "sobol", "bluenoise"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SamplerKind::Independent => "independent",
+            SamplerKind::Stratified => "stratified",
+            SamplerKind::Halton => "halton",
+            SamplerKind::Sobol => "sobol",
+            SamplerKind::BlueNoise => "bluenoise",
+        }
+    }
+
+    // A sampler for a render with the given seed and samples per pixel
+    pub fn create(self, seed: u64, samples: usize) -> Box<dyn Sampler> {
+        let state = State {
+            seed,
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+            rng: Rng::new(seed),
+        };
+
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler { state }),
+            SamplerKind::Stratified => Box::new(StratifiedSampler {
+                state,
+                samples: (samples as u32).max(1),
+            }),
+            SamplerKind::Halton => Box::new(HaltonSampler { state }),
+            SamplerKind::Sobol => Box::new(SobolSampler { state }),
+            SamplerKind::BlueNoise => Box::new(BlueNoiseSampler { state }),
+        }
+    }
+}
+
+impl FromStr for SamplerKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "independent" => Ok(SamplerKind::Independent),
+            "stratified" => Ok(SamplerKind::Stratified),
+            "halton" => Ok(SamplerKind::Halton),
+            "sobol" => Ok(SamplerKind::Sobol),
+            "bluenoise" => Ok(SamplerKind::BlueNoise),
+            _ => Err(()),
+        }
+    }
+}
+
+// Hash two values into one
+fn hash(a: u64, b: u64) -> u64 {
+    mix(a ^ mix(b))
+}
+
+// Float in [0, 1) from the high bits of an integer
+fn to_float(bits: u32) -> f32 {
+    (bits >> 8) as f32 / (1u32 << 24) as f32
+}
+
+// Where a sampler is in the sample space, shared by every sampler
+#[derive(Debug, Clone)]
+struct State {
+    seed: u64,
+    pixel: (usize, usize),
+    index: u32,
+    dimension: u64,
+    // Random numbers of the current sample, for jitter and dimensions beyond a sequence
+    rng: Rng,
+}
+
+impl State {
+    fn start(&mut self, pixel: (usize, usize), index: u32) {
+        let key = ((pixel.0 as u64) << 32) | pixel.1 as u64;
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+        self.rng = Rng::for_sample(self.seed, key, index);
+    }
+
+    // Move on to the next dimension, returning a seed for it that is the same for
+    // every sample of the pixel
+    fn next_dimension(&mut self) -> u64 {
+        let key = ((self.pixel.0 as u64) << 32) | self.pixel.1 as u64;
+        self.dimension += 1;
+        hash(hash(self.seed, key), self.dimension)
+    }
+}
+
+pub struct IndependentSampler {
+    state: State,
+}
+
+impl Sampler for IndependentSampler {
+    fn start_sample(&mut self, pixel: (usize, usize), index: u32) {
+        self.state.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.state.rng.float()
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.state.rng.float(), self.state.rng.float())
+    }
+}
+
+// Random permutation of [0, n), different for every seed (Kensler, Correlated
+// Multi-Jittered Sampling)
+fn permute(mut i: u32, n: u32, seed: u32) -> u32 {
+    let mut w = n - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+
+    // Bijective on the next power of two, retried until the result lands below n
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+
+        if i < n {
+            return (i + seed % n) % n;
+        }
+    }
+}
+
+// Splits every dimension into as many strata as there are samples per pixel
+// Samples past that count start another round of strata in a new order
+pub struct StratifiedSampler {
+    state: State,
+    samples: u32,
+}
+
+impl StratifiedSampler {
+    // Stratum of the current sample among `count`, of which only the first `samples`
+    // are used each round
+    fn stratum(&mut self, count: u32) -> u32 {
+        let round = self.state.index / self.samples;
+        let seed = hash(self.state.next_dimension(), round as u64) as u32;
+        permute(self.state.index % self.samples, count, seed)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, pixel: (usize, usize), index: u32) {
+        self.state.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let stratum = self.stratum(self.samples);
+        (stratum as f32 + self.state.rng.float()) / self.samples as f32
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        // A grid of at least as many cells as samples, as square as possible
+        let columns = ((self.samples as f32).sqrt() as u32).max(1);
+        let rows = self.samples.div_ceil(columns);
+
+        let stratum = self.stratum(columns * rows);
+        let x = (stratum % columns) as f32 + self.state.rng.float();
+        let y = (stratum / columns) as f32 + self.state.rng.float();
+
+        (x / columns as f32, y / rows as f32)
+    }
+}
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+// Digits of the index in a base, mirrored around the radix point, each one permuted
+// depending on the digits before it (Owen scrambling)
+// Without scrambling the larger bases start with long runs of small values
+fn scrambled_radical_inverse(base: u32, mut index: u32, mut seed: u64) -> f32 {
+    let inverse = 1.0 / base as f64;
+    let mut digit_value = inverse;
+    let mut result = 0.0;
+
+    // Zero digits past the end of the index get scrambled too, down to float precision
+    while digit_value > 1e-8 {
+        let digit = index % base;
+        result += permute(digit, base, seed as u32) as f64 * digit_value;
+
+        seed = hash(seed, digit as u64);
+        index /= base;
+        digit_value *= inverse;
+    }
+
+    (result as f32).min(1.0 - f32::EPSILON / 2.0)
+}
+
+// Halton sequence with a prime base per dimension
+// Dimensions beyond the table of primes fall back to independent random numbers
+pub struct HaltonSampler {
+    state: State,
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, pixel: (usize, usize), index: u32) {
+        self.state.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let dimension = self.state.dimension as usize;
+        let seed = self.state.next_dimension();
+
+        match PRIMES.get(dimension) {
+            Some(&base) => scrambled_radical_inverse(base, self.state.index, seed),
+            None => self.state.rng.float(),
+        }
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+// Nested uniform scramble of the reversed bits of a value (Burley, Practical Hash-based
+// Owen Scrambling)
+fn laine_karras(mut x: u32, seed: u32) -> u32 {
+    x ^= x.wrapping_mul(0x3d20_adea);
+    x = x.wrapping_add(seed);
+    x = x.wrapping_mul((seed >> 16) | 1);
+    x ^= x.wrapping_mul(0x0552_6c56);
+    x ^= x.wrapping_mul(0x53a2_2864);
+    x
+}
+
+fn owen_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras(x.reverse_bits(), seed).reverse_bits()
+}
+
+// Second dimension of the Sobol sequence, the first is the index with its bits reversed
+fn sobol_1(mut index: u32) -> u32 {
+    let mut direction = 1u32 << 31;
+    let mut result = 0;
+
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= direction;
+        }
+
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+
+    result
+}
+
+// First one or two Sobol dimensions of an index, shuffled and Owen scrambled by a seed
+fn scrambled_sobol_1d(index: u32, seed: u64) -> f32 {
+    let index = owen_scramble(index, seed as u32);
+    to_float(owen_scramble(index.reverse_bits(), (seed >> 32) as u32))
+}
+
+fn scrambled_sobol_2d(index: u32, seed: u64) -> (f32, f32) {
+    let index = owen_scramble(index, seed as u32);
+    let x = owen_scramble(index.reverse_bits(), hash(seed, 1) as u32);
+    let y = owen_scramble(sobol_1(index), hash(seed, 2) as u32);
+
+    (to_float(x), to_float(y))
+}
+
+// Every dimension, or pair of them, is a different shuffle and scramble of the same
+// two Sobol dimensions, which keeps them uncorrelated without a table of directions
+pub struct SobolSampler {
+    state: State,
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, pixel: (usize, usize), index: u32) {
+        self.state.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let seed = self.state.next_dimension();
+        scrambled_sobol_1d(self.state.index, seed)
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let seed = self.state.next_dimension();
+        scrambled_sobol_2d(self.state.index, seed)
+    }
+}
+
+const MASK_SIZE: usize = 64;
+
+// Ranks of a blue noise dither mask, as values in (0, 1), made once when first used
+fn blue_noise_mask() -> &'static [f32] {
+    static MASK: OnceLock<Vec<f32>> = OnceLock::new();
+    MASK.get_or_init(void_and_cluster)
+}
+
+// Ulichney's void-and-cluster method on a torus: points are ranked by repeatedly taking
+// away the one in the tightest cluster or filling the largest void, measured by the
+// Gaussian weighted density of the points around each pixel
+fn void_and_cluster() -> Vec<f32> {
+    const SIZE: usize = MASK_SIZE;
+    const PIXELS: usize = SIZE * SIZE;
+    const SIGMA: f32 = 1.5;
+
+    // Gaussian of every offset, wrapping around the edges
+    let gaussian: Vec<f32> = (0..PIXELS)
+        .map(|i| {
+            let (dx, dy) = (i % SIZE, i / SIZE);
+            let (dx, dy) = (dx.min(SIZE - dx) as f32, dy.min(SIZE - dy) as f32);
+            (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp()
+        })
+        .collect();
+
+    let update = |density: &mut [f32], p: usize, sign: f32| {
+        let (px, py) = (p % SIZE, p / SIZE);
+        for (q, d) in density.iter_mut().enumerate() {
+            let dx = (q % SIZE + SIZE - px) % SIZE;
+            let dy = (q / SIZE + SIZE - py) % SIZE;
+            *d += sign * gaussian[dy * SIZE + dx];
+        }
+    };
+
+    let tightest_cluster = |points: &[bool], density: &[f32]| {
+        (0..PIXELS)
+            .filter(|&p| points[p])
+            .max_by(|&a, &b| density[a].total_cmp(&density[b]))
+            .unwrap()
+    };
+
+    let largest_void = |points: &[bool], density: &[f32]| {
+        (0..PIXELS)
+            .filter(|&p| !points[p])
+            .min_by(|&a, &b| density[a].total_cmp(&density[b]))
+            .unwrap()
+    };
+
+    // Start from a tenth of the pixels chosen at random
+    let mut rng = Rng::new(0);
+    let mut points = vec![false; PIXELS];
+    let mut density = vec![0.0; PIXELS];
+    let initial = PIXELS / 10;
+
+    let mut count = 0;
+    while count < initial {
+        let p = rng.next_u32() as usize % PIXELS;
+        if !points[p] {
+            points[p] = true;
+            update(&mut density, p, 1.0);
+            count += 1;
+        }
+    }
+
+    // Spread them out, moving the most crowded point into the emptiest spot until the
+    // point taken away is the one put back
+    loop {
+        let cluster = tightest_cluster(&points, &density);
+        points[cluster] = false;
+        update(&mut density, cluster, -1.0);
+
+        let void = largest_void(&points, &density);
+        points[void] = true;
+        update(&mut density, void, 1.0);
+
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0; PIXELS];
+
+    // The initial points get the lowest ranks, the most crowded last
+    let (mut remaining, mut remaining_density) = (points.clone(), density.clone());
+    for rank in (0..initial).rev() {
+        let cluster = tightest_cluster(&remaining, &remaining_density);
+        remaining[cluster] = false;
+        update(&mut remaining_density, cluster, -1.0);
+        ranks[cluster] = rank;
+    }
+
+    // Every other pixel is ranked in the order the largest voids get filled
+    for rank in initial..PIXELS {
+        let void = largest_void(&points, &density);
+        points[void] = true;
+        update(&mut density, void, 1.0);
+        ranks[void] = rank;
+    }
+
+    ranks
+        .iter()
+        .map(|&rank| (rank as f32 + 0.5) / PIXELS as f32)
+        .collect()
+}
+
+// Owen scrambled Sobol points with the same scrambles for every pixel, each pixel
+// rotating them by its value in the mask, looked up at a different offset per dimension
+// At low sample counts the error of neighbouring pixels differs, showing up as fine
+// grain instead of blotches
+pub struct BlueNoiseSampler {
+    state: State,
+}
+
+impl BlueNoiseSampler {
+    // Seed of the next dimension, shared by every pixel, and the pixel's rotations
+    fn next_dimension(&mut self) -> (u64, f32, f32) {
+        self.state.dimension += 1;
+        let seed = hash(self.state.seed, self.state.dimension);
+
+        let mask = blue_noise_mask();
+        let (x, y) = self.state.pixel;
+        let lookup = |offset: u64| {
+            let ox = (offset % MASK_SIZE as u64) as usize;
+            let oy = ((offset >> 32) % MASK_SIZE as u64) as usize;
+            mask[((y + oy) % MASK_SIZE) * MASK_SIZE + (x + ox) % MASK_SIZE]
+        };
+
+        (seed, lookup(hash(seed, 3)), lookup(hash(seed, 4)))
+    }
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn start_sample(&mut self, pixel: (usize, usize), index: u32) {
+        self.state.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let (seed, rotation, _) = self.next_dimension();
+        (scrambled_sobol_1d(self.state.index, seed) + rotation).fract()
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let (seed, rx, ry) = self.next_dimension();
+        let (x, y) = scrambled_sobol_2d(self.state.index, seed);
+        ((x + rx).fract(), (y + ry).fract())
+    }
+}
+
+// Point in the unit disk, from Shirley and Chiu's concentric mapping of the square
+pub fn in_unit_disk(sampler: &mut dyn Sampler) -> Vector3 {
+    let (u, v) = sampler.get_2d();
+    let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return Vector3::default();
+    }
+
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+
+    Vector3::new(r * theta.cos(), r * theta.sin(), 0.0)
+}
+
+// Uniformly distributed point on the unit sphere
+pub fn on_unit_sphere(sampler: &mut dyn Sampler) -> Vector3 {
+    let (u, v) = sampler.get_2d();
+    let z = 1.0 - 2.0 * u;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * v;
+
+    Vector3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+// Uniformly distributed point inside the unit sphere
+pub fn in_unit_sphere(sampler: &mut dyn Sampler) -> Vector3 {
+    on_unit_sphere(sampler) * sampler.get_1d().cbrt()
+}
+
+// Unit vector on the +z hemisphere with density cos(theta) / pi, the unit disk lifted up
+// onto it
+pub fn cosine_hemisphere(sampler: &mut dyn Sampler) -> Vector3 {
+    let d = in_unit_disk(sampler);
+    let z = (1.0 - d.x * d.x - d.y * d.y).max(0.0).sqrt();
+
+    Vector3::new(d.x, d.y, z)
+}
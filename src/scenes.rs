@@ -1,15 +1,67 @@
+use crate::animation::{Animated, Keyframes};
 use crate::camera::Camera;
-use crate::material::{Dielectric, Lambertian, Metal};
-use crate::moving_sphere::MovingSphere;
-use crate::random_float;
+use crate::environment::{Constant, Gradient};
+use crate::light::Lights;
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Metal};
+use crate::bvh::BvhNode;
+use crate::random::Rng;
 use crate::sphere::Sphere;
 use crate::vector::Vector3;
 use crate::world::World;
-use std::rc::Rc;
-use crate::bvh::BvhNode;
+use std::sync::Arc;
 use crate::hit::Hit;
 
-pub fn basic_scene(width: usize, height: usize) -> (Box<dyn Hit>, Camera) {
+// Builds a scene for an image of the given width and height, with the camera shutter
+// open between the two given times, drawing any random placement from the generator
+// Returns the objects, the camera and the lights among and around them
+pub type SceneBuilder =
+    fn(usize, usize, (f32, f32), &mut Rng) -> (Box<dyn Hit>, Camera, Lights);
+
+pub struct Scene {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub build: SceneBuilder,
+}
+
+// Every scene selectable by name
+pub const SCENES: &[Scene] = &[
+    Scene {
+        name: "basic",
+        description: "Diffuse, metal and glass spheres on a yellow ground",
+        build: basic_scene,
+    },
+    Scene {
+        name: "random",
+        description: "The book cover: a field of small random spheres",
+        build: random_scene,
+    },
+    Scene {
+        name: "colored_spheres",
+        description: "An 11x11x11 cube of spheres colored by position",
+        build: colored_sphere_scene,
+    },
+    Scene {
+        name: "bouncing",
+        description: "Three balls bouncing out of step, for animations",
+        build: bouncing_scene,
+    },
+    Scene {
+        name: "lamps",
+        description: "Spheres lit only by two small glowing spheres, under a black sky",
+        build: lamps_scene,
+    },
+];
+
+pub fn find_scene(name: &str) -> Option<&'static Scene> {
+    SCENES.iter().find(|scene| scene.name == name)
+}
+
+pub fn basic_scene(
+    width: usize,
+    height: usize,
+    shutter: (f32, f32),
+    rng: &mut Rng,
+) -> (Box<dyn Hit>, Camera, Lights) {
     let eye = Vector3::new(4.0, 4.0, 4.0);
     let center = Vector3::new(0.0, 0.0, 0.0);
     let up = Vector3::unit_y();
@@ -25,42 +77,50 @@ pub fn basic_scene(width: usize, height: usize) -> (Box<dyn Hit>, Camera) {
         width as f32 / height as f32,
         aperture,
         focus,
-        0.0,
-        1.0,
+        shutter.0,
+        shutter.1,
     );
 
     let mut world = World::new();
     world.add(Sphere::new(
         Vector3::new(0.0, -100.5, 0.0),
         100.0,
-        Rc::new(Lambertian::new(Vector3::new(0.8, 0.8, 0.0))),
+        Arc::new(Lambertian::new(Vector3::new(0.8, 0.8, 0.0))),
     ));
     world.add(Sphere::new(
         Vector3::new(0.0, 0.0, 0.0),
         0.5,
-        Rc::new(Lambertian::new(Vector3::new(0.1, 0.2, 0.5))),
+        Arc::new(Lambertian::new(Vector3::new(0.1, 0.2, 0.5))),
     ));
     world.add(Sphere::new(
         Vector3::new(1.0, 0.0, 0.0),
         0.5,
-        Rc::new(Metal::new(Vector3::new(0.8, 0.6, 0.2), 0.2)),
+        Arc::new(Metal::new(Vector3::new(0.8, 0.6, 0.2), 0.2)),
     ));
     world.add(Sphere::new(
         Vector3::new(-1.0, 0.0, 0.0),
         0.5,
-        Rc::new(Dielectric::new(1.5)),
+        Arc::new(Dielectric::new(1.5)),
     ));
     world.add(Sphere::new(
         Vector3::new(-1.0, 0.0, 0.0),
         -0.45,
-        Rc::new(Dielectric::new(1.5)),
+        Arc::new(Dielectric::new(1.5)),
     ));
 
-    //(Box::new(BvhNode::new(&mut world.hits, 0.0, 1.0)), camera)
-    (Box::new(world), camera)
+    (
+        Box::new(BvhNode::new(world.hits, shutter.0, shutter.1, rng)),
+        camera,
+        Lights::new(Box::new(Gradient::sky())),
+    )
 }
 
-pub fn random_scene(width: usize, height: usize) -> (Box<dyn Hit>, Camera) {
+pub fn random_scene(
+    width: usize,
+    height: usize,
+    shutter: (f32, f32),
+    rng: &mut Rng,
+) -> (Box<dyn Hit>, Camera, Lights) {
     let eye = Vector3::new(13.0, 2.0, 3.0);
     let center = Vector3::new(0.0, 0.0, 0.0);
     let up = Vector3::unit_y();
@@ -76,24 +136,24 @@ pub fn random_scene(width: usize, height: usize) -> (Box<dyn Hit>, Camera) {
         width as f32 / height as f32,
         aperture,
         focus,
-        0.0,
-        1.0,
+        shutter.0,
+        shutter.1,
     );
 
     let mut world = World::new();
     world.add(Sphere::new(
         Vector3::new(0.0, -1000.0, 0.0),
         1000.0,
-        Rc::new(Lambertian::new(Vector3::new(0.5, 0.5, 0.5))),
+        Arc::new(Lambertian::new(Vector3::new(0.5, 0.5, 0.5))),
     ));
 
     for a in -11..11 {
         for b in -11..11 {
-            let random_mat = random_float();
+            let random_mat = rng.float();
             let sp = Vector3::new(
-                a as f32 + 0.9 * random_float(),
+                a as f32 + 0.9 * rng.float(),
                 0.2,
-                b as f32 + 0.9 * random_float(),
+                b as f32 + 0.9 * rng.float(),
             );
 
             if (sp - Vector3::new(4.0, 0.2, 0.0)).length() > 0.9 {
@@ -101,27 +161,27 @@ pub fn random_scene(width: usize, height: usize) -> (Box<dyn Hit>, Camera) {
                     world.add(Sphere::new(
                         sp,
                         0.2,
-                        Rc::new(Lambertian::new(Vector3::new(
-                            random_float() * random_float(),
-                            random_float() * random_float(),
-                            random_float() * random_float(),
+                        Arc::new(Lambertian::new(Vector3::new(
+                            rng.float() * rng.float(),
+                            rng.float() * rng.float(),
+                            rng.float() * rng.float(),
                         ))),
                     ));
                 } else if random_mat < 0.95 {
                     world.add(Sphere::new(
                         sp,
                         0.2,
-                        Rc::new(Metal::new(
+                        Arc::new(Metal::new(
                             Vector3::new(
-                                0.5 * (random_float() + 1.0),
-                                0.5 * (random_float() + 1.0),
-                                0.5 * (random_float() + 1.0),
+                                0.5 * (rng.float() + 1.0),
+                                0.5 * (rng.float() + 1.0),
+                                0.5 * (rng.float() + 1.0),
                             ),
-                            0.5 * random_float(),
+                            0.5 * rng.float(),
                         )),
                     ));
                 } else {
-                    world.add(Sphere::new(sp, 0.2, Rc::new(Dielectric::new(1.5))));
+                    world.add(Sphere::new(sp, 0.2, Arc::new(Dielectric::new(1.5))));
                 }
             }
         }
@@ -130,14 +190,22 @@ pub fn random_scene(width: usize, height: usize) -> (Box<dyn Hit>, Camera) {
     world.add(Sphere::new(
         Vector3::new(0.0, 0.9, 0.0),
         1.0,
-        Rc::new(Metal::new(Vector3::new(0.0, 0.5, 0.9), 0.0)),
+        Arc::new(Metal::new(Vector3::new(0.0, 0.5, 0.9), 0.0)),
     ));
 
-    // (Box::new(BvhNode::new(&mut world.hits, 0.0, 1.0)), camera)
-    (Box::new(world), camera)
+    (
+        Box::new(BvhNode::new(world.hits, shutter.0, shutter.1, rng)),
+        camera,
+        Lights::new(Box::new(Gradient::sky())),
+    )
 }
 
-pub fn colored_sphere_scene(width: usize, height: usize) -> (Box<dyn Hit>, Camera) {
+pub fn colored_sphere_scene(
+    width: usize,
+    height: usize,
+    shutter: (f32, f32),
+    rng: &mut Rng,
+) -> (Box<dyn Hit>, Camera, Lights) {
     let eye = Vector3::new(-5.5, 5.5, 5.5);
     let center = Vector3::new(0.0, 0.0, 0.0);
     let up = Vector3::unit_y();
@@ -153,8 +221,8 @@ pub fn colored_sphere_scene(width: usize, height: usize) -> (Box<dyn Hit>, Camer
         width as f32 / height as f32,
         aperture,
         focus,
-        0.0,
-        1.0,
+        shutter.0,
+        shutter.1,
     );
 
     let mut world = World::new();
@@ -162,7 +230,7 @@ pub fn colored_sphere_scene(width: usize, height: usize) -> (Box<dyn Hit>, Camer
     world.add(Sphere::new(
         Vector3::new(0.0, -1003.0, 0.0),
         1000.0,
-        Rc::new(Lambertian::new(Vector3::new(0.8, 0.8, 0.8))),
+        Arc::new(Lambertian::new(Vector3::new(0.8, 0.8, 0.8))),
     ));
 
     for a in -5..=5 {
@@ -175,11 +243,153 @@ pub fn colored_sphere_scene(width: usize, height: usize) -> (Box<dyn Hit>, Camer
                 );
                 let sp = Vector3::new(a as f32 * 0.5, b as f32 * 0.5, c as f32 * 0.5);
 
-                world.add(Sphere::new(sp, 0.2, Rc::new(Lambertian::new(color))));
+                world.add(Sphere::new(sp, 0.2, Arc::new(Lambertian::new(color))));
             }
         }
     }
 
-    // (Box::new(BvhNode::new(world.hits, 0.0, 1.0)), camera)
-    (Box::new(world), camera)
+    (
+        Box::new(BvhNode::new(world.hits, shutter.0, shutter.1, rng)),
+        camera,
+        Lights::new(Box::new(Gradient::sky())),
+    )
+}
+
+pub fn bouncing_scene(
+    width: usize,
+    height: usize,
+    shutter: (f32, f32),
+    rng: &mut Rng,
+) -> (Box<dyn Hit>, Camera, Lights) {
+    let eye = Vector3::new(0.0, 2.0, 9.0);
+    let center = Vector3::new(0.0, 1.0, 0.0);
+    let up = Vector3::unit_y();
+
+    let focus = (eye - center).length();
+    let aperture = 0.0;
+
+    let camera = Camera::new(
+        eye,
+        center,
+        up,
+        40.0,
+        width as f32 / height as f32,
+        aperture,
+        focus,
+        shutter.0,
+        shutter.1,
+    );
+
+    let mut world = World::new();
+    world.add(Sphere::new(
+        Vector3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::new(Vector3::new(0.5, 0.5, 0.5))),
+    ));
+
+    let colors = [
+        Vector3::new(0.8, 0.2, 0.1),
+        Vector3::new(0.1, 0.6, 0.2),
+        Vector3::new(0.1, 0.2, 0.8),
+    ];
+
+    // Every bounce is a parabola sampled into keyframes, repeating for ten seconds
+    let steps = 16;
+    for (i, &color) in colors.iter().enumerate() {
+        let x = (i as f32 - 1.0) * 2.0;
+        let period = 0.6 + 0.2 * i as f32;
+        let bounces = (10.0 / period).ceil() as usize;
+
+        let keys = (0..=bounces * steps)
+            .map(|k| {
+                let phase = (k % steps) as f32 / steps as f32;
+                let y = 8.0 * phase * (1.0 - phase);
+                (k as f32 * period / steps as f32, Vector3::new(x, y, 0.0))
+            })
+            .collect();
+
+        world.add(Animated::new(
+            Sphere::new(
+                Vector3::new(0.0, 0.5, 0.0),
+                0.5,
+                Arc::new(Lambertian::new(color)),
+            ),
+            Keyframes::new(keys),
+        ));
+    }
+
+    (
+        Box::new(BvhNode::new(world.hits, shutter.0, shutter.1, rng)),
+        camera,
+        Lights::new(Box::new(Gradient::sky())),
+    )
+}
+
+pub fn lamps_scene(
+    width: usize,
+    height: usize,
+    shutter: (f32, f32),
+    rng: &mut Rng,
+) -> (Box<dyn Hit>, Camera, Lights) {
+    let eye = Vector3::new(0.0, 1.5, 7.0);
+    let center = Vector3::new(0.0, 0.8, 0.0);
+    let up = Vector3::unit_y();
+
+    let focus = (eye - center).length();
+    let aperture = 0.0;
+
+    let camera = Camera::new(
+        eye,
+        center,
+        up,
+        45.0,
+        width as f32 / height as f32,
+        aperture,
+        focus,
+        shutter.0,
+        shutter.1,
+    );
+
+    let mut world = World::new();
+    world.add(Sphere::new(
+        Vector3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::new(Vector3::new(0.6, 0.6, 0.6))),
+    ));
+    world.add(Sphere::new(
+        Vector3::new(-1.6, 0.8, 0.0),
+        0.8,
+        Arc::new(Lambertian::new(Vector3::new(0.7, 0.3, 0.2))),
+    ));
+    world.add(Sphere::new(
+        Vector3::new(0.0, 0.8, -0.5),
+        0.8,
+        Arc::new(Metal::new(Vector3::new(0.8, 0.8, 0.8), 0.1)),
+    ));
+    world.add(Sphere::new(
+        Vector3::new(1.6, 0.8, 0.0),
+        0.8,
+        Arc::new(Dielectric::new(1.5)),
+    ));
+
+    // A warm lamp high up on the left and a dimmer cool one low on the right
+    world.add_light(Sphere::new(
+        Vector3::new(-1.0, 2.6, 1.5),
+        0.3,
+        Arc::new(DiffuseLight::new(Vector3::new(1.0, 0.8, 0.6), 40.0)),
+    ));
+    world.add_light(Sphere::new(
+        Vector3::new(2.5, 0.4, 1.5),
+        0.2,
+        Arc::new(DiffuseLight::new(Vector3::new(0.5, 0.7, 1.0), 30.0)),
+    ));
+
+    (
+        Box::new(BvhNode::new(world.hits, shutter.0, shutter.1, rng)),
+        camera,
+        Lights {
+            environment: Box::new(Constant::new(Vector3::default())),
+            emitters: world.lights,
+        },
+    )
 }
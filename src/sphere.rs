@@ -2,17 +2,91 @@ use crate::aabb::Aabb;
 use crate::hit::{Hit, HitRecord};
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::sampler::{on_unit_sphere, Sampler};
+use crate::stats::count_primitive_test;
 use crate::vector::Vector3;
-use std::rc::Rc;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+// Pick a unit direction from a point towards a sphere, uniformly over the cone of
+// directions it covers, or over its surface from inside
+// Returns the direction and its density per solid angle
+pub fn sample_sphere(
+    center: Vector3,
+    radius: f32,
+    origin: Vector3,
+    sampler: &mut dyn Sampler,
+) -> Option<(Vector3, f32)> {
+    let radius = radius.abs();
+    let to_center = center - origin;
+    let distance_squared = to_center.squared_length();
+
+    if distance_squared <= radius * radius {
+        let point = center + on_unit_sphere(sampler) * radius;
+        let direction = (point - origin).normalize();
+        let pdf = sphere_pdf(center, radius, origin, direction);
+        return Some((direction, pdf)).filter(|&(_, pdf)| pdf > 0.0 && pdf.is_finite());
+    }
+
+    let (u, v) = sampler.get_2d();
+    let (sin_max_squared, cos_max) = cone(radius, distance_squared);
+    let cos_theta = 1.0 - u * (1.0 - cos_max);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * v;
+
+    let w = to_center / distance_squared.sqrt();
+    let (a, b) = w.perpendiculars();
+    let direction = a * (sin_theta * phi.cos()) + b * (sin_theta * phi.sin()) + w * cos_theta;
+
+    Some((direction, cone_pdf(sin_max_squared, cos_max)))
+}
+
+// Density of sample_sphere picking a unit direction
+pub fn sphere_pdf(center: Vector3, radius: f32, origin: Vector3, direction: Vector3) -> f32 {
+    let radius = radius.abs();
+    let to_center = center - origin;
+    let distance_squared = to_center.squared_length();
+
+    if distance_squared <= radius * radius {
+        // Uniform over the area, seen at the point where the direction leaves the sphere
+        let b = to_center.dot(direction);
+        let t = b + (b * b - distance_squared + radius * radius).max(0.0).sqrt();
+        let normal = (origin + direction * t - center) / radius;
+        let cosine = normal.dot(direction).abs();
+        if cosine <= 0.0 {
+            return 0.0;
+        }
+
+        return t * t / (cosine * 4.0 * PI * radius * radius);
+    }
+
+    let (sin_max_squared, cos_max) = cone(radius, distance_squared);
+    if direction.dot(to_center) < cos_max * distance_squared.sqrt() {
+        return 0.0;
+    }
+
+    cone_pdf(sin_max_squared, cos_max)
+}
+
+// Squared sine and cosine of the half angle of the cone a sphere fills
+fn cone(radius: f32, distance_squared: f32) -> (f32, f32) {
+    let sin_max_squared = (radius * radius / distance_squared).min(1.0);
+    (sin_max_squared, (1.0 - sin_max_squared).sqrt())
+}
+
+// Uniform density over a cone, 1 - cos written to keep its precision for small cones
+fn cone_pdf(sin_max_squared: f32, cos_max: f32) -> f32 {
+    1.0 / (2.0 * PI * sin_max_squared / (1.0 + cos_max))
+}
 
 pub struct Sphere {
     center: Vector3,
     radius: f32,
-    material: Rc<dyn Material>,
+    material: Arc<dyn Material>,
 }
 
 impl Sphere {
-    pub fn new(center: Vector3, radius: f32, material: Rc<dyn Material>) -> Self {
+    pub fn new(center: Vector3, radius: f32, material: Arc<dyn Material>) -> Self {
         Sphere {
             center,
             radius,
@@ -23,6 +97,8 @@ impl Sphere {
 
 impl Hit for Sphere {
     fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        count_primitive_test();
+
         let result: Option<HitRecord> = None;
 
         let oc = ray.origin - self.center;
@@ -52,10 +128,23 @@ impl Hit for Sphere {
         result
     }
 
-    fn bounding_box(&self, t0: f32, t1: f32) -> Option<Aabb> {
+    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<Aabb> {
         Some(Aabb::new(
             self.center - Vector3::new(self.radius, self.radius, self.radius),
             self.center + Vector3::new(self.radius, self.radius, self.radius),
         ))
     }
+
+    fn sample_direction(
+        &self,
+        origin: Vector3,
+        _time: f32,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Vector3, f32)> {
+        sample_sphere(self.center, self.radius, origin, sampler)
+    }
+
+    fn direction_pdf(&self, origin: Vector3, direction: Vector3, _time: f32) -> f32 {
+        sphere_pdf(self.center, self.radius, origin, direction)
+    }
 }
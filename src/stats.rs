@@ -0,0 +1,176 @@
+use std::cell::Cell;
+use std::fmt::Write;
+use std::ops::AddAssign;
+use std::time::Duration;
+
+// Work done while rendering, counted per thread and summed afterwards
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Counters {
+    pub primary_rays: u64,
+    pub secondary_rays: u64,
+    // Rays towards lights, testing only whether they are blocked
+    pub shadow_rays: u64,
+    pub bvh_nodes_visited: u64,
+    pub primitive_tests: u64,
+}
+
+impl AddAssign for Counters {
+    fn add_assign(&mut self, rhs: Counters) {
+        self.primary_rays += rhs.primary_rays;
+        self.secondary_rays += rhs.secondary_rays;
+        self.shadow_rays += rhs.shadow_rays;
+        self.bvh_nodes_visited += rhs.bvh_nodes_visited;
+        self.primitive_tests += rhs.primitive_tests;
+    }
+}
+
+thread_local! {
+    // Thread local so the hot paths never contend on shared counters
+    static COUNTERS: Cell<Counters> = Cell::new(Counters::default());
+}
+
+fn update<F: FnOnce(&mut Counters)>(f: F) {
+    COUNTERS.with(|counters| {
+        let mut c = counters.get();
+        f(&mut c);
+        counters.set(c);
+    });
+}
+
+pub fn count_ray(primary: bool) {
+    update(|c| {
+        if primary {
+            c.primary_rays += 1;
+        } else {
+            c.secondary_rays += 1;
+        }
+    });
+}
+
+pub fn count_shadow_ray() {
+    update(|c| c.shadow_rays += 1);
+}
+
+pub fn count_bvh_node() {
+    update(|c| c.bvh_nodes_visited += 1);
+}
+
+pub fn count_primitive_test() {
+    update(|c| c.primitive_tests += 1);
+}
+
+// Return the current thread's counters and reset them
+pub fn take_counters() -> Counters {
+    COUNTERS.with(|counters| counters.replace(Counters::default()))
+}
+
+// Summary of a whole render
+#[derive(Debug, Clone)]
+pub struct RenderStats {
+    pub scene: String,
+    pub width: usize,
+    pub height: usize,
+    pub samples: usize,
+    pub threads: usize,
+    pub seed: u64,
+    pub build_time: Duration,
+    pub render_time: Duration,
+    pub counters: Counters,
+}
+
+impl RenderStats {
+    pub fn rays(&self) -> u64 {
+        let c = &self.counters;
+        c.primary_rays + c.secondary_rays + c.shadow_rays
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        self.rays() as f64 / self.render_time.as_secs_f64().max(1e-9)
+    }
+
+    // Average number of segments per camera path
+    pub fn average_path_length(&self) -> f64 {
+        let c = &self.counters;
+        (c.primary_rays + c.secondary_rays) as f64 / c.primary_rays.max(1) as f64
+    }
+
+    pub fn summary(&self) -> String {
+        let c = &self.counters;
+        let rows = [
+            ("Scene", self.scene.clone()),
+            ("Resolution", format!("{}x{}", self.width, self.height)),
+            ("Samples per pixel", self.samples.to_string()),
+            ("Threads", self.threads.to_string()),
+            ("Build time", format!("{:?}", self.build_time)),
+            ("Render time", format!("{:?}", self.render_time)),
+            ("Primary rays", c.primary_rays.to_string()),
+            ("Secondary rays", c.secondary_rays.to_string()),
+            ("Shadow rays", c.shadow_rays.to_string()),
+            ("Rays per second", format!("{:.0}", self.rays_per_second())),
+            ("BVH nodes visited", c.bvh_nodes_visited.to_string()),
+            ("Primitive tests", c.primitive_tests.to_string()),
+            (
+                "Average path length",
+                format!("{:.3}", self.average_path_length()),
+            ),
+        ];
+
+        let lines: Vec<String> = rows
+            .iter()
+            .map(|(name, value)| format!("{:<21}{}", format!("{}:", name), value))
+            .collect();
+
+        lines.join("\n")
+    }
+
+    // Machine readable report, one flat JSON object
+    pub fn to_json(&self) -> String {
+        let c = &self.counters;
+        let fields = [
+            ("date", quote(&chrono::Utc::now().to_rfc3339())),
+            ("scene", quote(&self.scene)),
+            ("width", self.width.to_string()),
+            ("height", self.height.to_string()),
+            ("samples", self.samples.to_string()),
+            ("threads", self.threads.to_string()),
+            ("seed", self.seed.to_string()),
+            ("build_seconds", self.build_time.as_secs_f64().to_string()),
+            ("render_seconds", self.render_time.as_secs_f64().to_string()),
+            ("primary_rays", c.primary_rays.to_string()),
+            ("secondary_rays", c.secondary_rays.to_string()),
+            ("shadow_rays", c.shadow_rays.to_string()),
+            ("rays_per_second", self.rays_per_second().to_string()),
+            ("bvh_nodes_visited", c.bvh_nodes_visited.to_string()),
+            ("primitive_tests", c.primitive_tests.to_string()),
+            (
+                "average_path_length",
+                self.average_path_length().to_string(),
+            ),
+        ];
+
+        let lines: Vec<String> = fields
+            .iter()
+            .map(|(name, value)| format!("  \"{}\": {}", name, value))
+            .collect();
+
+        format!("{{\n{}\n}}\n", lines.join(",\n"))
+    }
+}
+
+// Quote and escape a string as a JSON string literal
+fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+    out
+}
@@ -0,0 +1,228 @@
+use crate::image::{luminance, Framebuffer};
+use crate::vector::Vector3;
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Tonemapper {
+    // Clip anything above 1
+    None,
+    // L / (1 + L) on luminance
+    Reinhard,
+    // Reinhard that maps the white point luminance to 1
+    ExtendedReinhard { white: f32 },
+    // Narkowicz's fit of the ACES filmic curve
+    Aces,
+    // John Hable's Uncharted 2 filmic curve
+    Hable,
+    // Minimal AgX: log encoding in a desaturating inset space and a sigmoid
+    Agx,
+}
+
+impl Tonemapper {
+    // Map scene-referred linear radiance to linear display values in [0, 1]
+    pub fn apply(self, col: Vector3) -> Vector3 {
+        match self {
+            Tonemapper::None => col,
+            Tonemapper::Reinhard => scale_luminance(col, |l| l / (1.0 + l)),
+            Tonemapper::ExtendedReinhard { white } => {
+                scale_luminance(col, |l| l * (1.0 + l / (white * white)) / (1.0 + l))
+            }
+            Tonemapper::Aces => per_channel(col, aces),
+            Tonemapper::Hable => {
+                let white = hable(11.2);
+                per_channel(col, |x| hable(2.0 * x) / white)
+            }
+            Tonemapper::Agx => agx(col),
+        }
+    }
+}
+
+impl FromStr for Tonemapper {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Tonemapper::None),
+            "reinhard" => Ok(Tonemapper::Reinhard),
+            "reinhard-extended" => Ok(Tonemapper::ExtendedReinhard { white: 4.0 }),
+            "aces" => Ok(Tonemapper::Aces),
+            "hable" => Ok(Tonemapper::Hable),
+            "agx" => Ok(Tonemapper::Agx),
+            _ => Err(()),
+        }
+    }
+}
+
+// Display encoding applied after tonemapping
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Transfer {
+    // The piecewise sRGB OETF
+    Srgb,
+    // A pure power curve, 1 / gamma
+    Gamma(f32),
+}
+
+impl Transfer {
+    pub fn encode(self, x: f32) -> f32 {
+        match self {
+            Transfer::Srgb => {
+                if x <= 0.003_130_8 {
+                    12.92 * x
+                } else {
+                    1.055 * x.powf(1.0 / 2.4) - 0.055
+                }
+            }
+            Transfer::Gamma(gamma) => x.powf(1.0 / gamma),
+        }
+    }
+
+    // Derivative of encode, how much a change in linear value moves the display value
+    pub fn slope(self, x: f32) -> f32 {
+        match self {
+            Transfer::Srgb => {
+                if x <= 0.003_130_8 {
+                    12.92
+                } else {
+                    1.055 / 2.4 * x.powf(1.0 / 2.4 - 1.0)
+                }
+            }
+            Transfer::Gamma(gamma) => x.powf(1.0 / gamma - 1.0) / gamma,
+        }
+    }
+
+    // Inverse of encode, from display values back to linear
+    pub fn decode(self, x: f32) -> f32 {
+        match self {
+            Transfer::Srgb => {
+                if x <= 0.040_45 {
+                    x / 12.92
+                } else {
+                    ((x + 0.055) / 1.055).powf(2.4)
+                }
+            }
+            Transfer::Gamma(gamma) => x.powf(gamma),
+        }
+    }
+}
+
+impl FromStr for Transfer {
+    type Err = ();
+
+    // Either "srgb" or a gamma value
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "srgb" {
+            return Ok(Transfer::Srgb);
+        }
+
+        match s.parse::<f32>() {
+            Ok(gamma) if gamma > 0.0 => Ok(Transfer::Gamma(gamma)),
+            _ => Err(()),
+        }
+    }
+}
+
+// Post-process stage turning linear radiance into display values
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Tonemap {
+    // Exposure adjustment in stops
+    pub exposure: f32,
+    pub tonemapper: Tonemapper,
+    pub transfer: Transfer,
+}
+
+impl Default for Tonemap {
+    fn default() -> Self {
+        Tonemap {
+            exposure: 0.0,
+            tonemapper: Tonemapper::None,
+            transfer: Transfer::Srgb,
+        }
+    }
+}
+
+impl Tonemap {
+    // Pass values through untouched, for images that are already display values
+    pub fn identity() -> Self {
+        Tonemap {
+            exposure: 0.0,
+            tonemapper: Tonemapper::None,
+            transfer: Transfer::Gamma(1.0),
+        }
+    }
+
+    // Encoded display color in [0, 1]
+    pub fn apply(&self, col: Vector3) -> Vector3 {
+        let col = self.tonemapper.apply(col * 2f32.powf(self.exposure));
+
+        per_channel(col, |x| self.transfer.encode(x.clamp(0.0, 1.0)))
+    }
+
+    pub fn apply_image(&self, image: &Framebuffer<Vector3>) -> Framebuffer<Vector3> {
+        image.map(|col| self.apply(col))
+    }
+}
+
+fn per_channel<F: Fn(f32) -> f32>(col: Vector3, f: F) -> Vector3 {
+    Vector3::new(f(col.x), f(col.y), f(col.z))
+}
+
+// Tonemap luminance and scale the color to match, keeping its hue
+fn scale_luminance<F: Fn(f32) -> f32>(col: Vector3, f: F) -> Vector3 {
+    let l = luminance(col);
+    if l <= 0.0 {
+        return Vector3::default();
+    }
+
+    col * (f(l) / l)
+}
+
+fn aces(x: f32) -> f32 {
+    let x = x.max(0.0);
+    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+}
+
+fn hable(x: f32) -> f32 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    let x = x.max(0.0);
+
+    ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+}
+
+// Multiply by a 3x3 matrix given row by row
+fn transform(m: [[f32; 3]; 3], v: Vector3) -> Vector3 {
+    Vector3::new(
+        m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+        m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+        m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+    )
+}
+
+fn agx(col: Vector3) -> Vector3 {
+    const INSET: [[f32; 3]; 3] = [
+        [0.842_479_06, 0.078_433_6, 0.079_223_745],
+        [0.042_328_242, 0.878_468_6, 0.079_166_13],
+        [0.042_375_655, 0.078_433_6, 0.879_143],
+    ];
+    const OUTSET: [[f32; 3]; 3] = [
+        [1.196_879, -0.098_020_88, -0.099_029_74],
+        [-0.052_896_85, 1.151_903_1, -0.098_961_18],
+        [-0.052_971_635, -0.098_043_45, 1.151_073_7],
+    ];
+    const MIN_EV: f32 = -12.473_93;
+    const MAX_EV: f32 = 4.026_069;
+
+    let v = transform(INSET, col);
+
+    // Log2 encoding over the covered range of stops, then the default contrast sigmoid
+    let v = per_channel(v, |x| {
+        let x = (x.max(1e-10).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+        let x2 = x * x;
+        let x4 = x2 * x2;
+
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232
+    });
+
+    // Back to linear display values
+    per_channel(transform(OUTSET, v), |x| x.max(0.0).powf(2.2))
+}
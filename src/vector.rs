@@ -54,6 +54,19 @@ impl Vector3 {
         }
     }
 
+    // Two unit vectors perpendicular to this unit vector and to each other, after Duff et
+    // al., "Building an Orthonormal Basis, Revisited"
+    pub fn perpendiculars(&self) -> (Vector3, Vector3) {
+        let sign = 1f32.copysign(self.z);
+        let a = -1.0 / (sign + self.z);
+        let b = self.x * self.y * a;
+
+        (
+            Vector3::new(1.0 + sign * self.x * self.x * a, sign * b, -sign * self.x),
+            Vector3::new(b, sign + self.y * self.y * a, -self.y),
+        )
+    }
+
     pub fn reflect(&self, other: Vector3) -> Vector3 {
         *self - (other * 2.0 * self.dot(other))
     }
@@ -63,11 +76,11 @@ impl Vector3 {
         let dot = unit.dot(other);
 
         let discriminant = 1.0 - refractive_index * refractive_index * (1.0 - dot * dot);
-        return if discriminant > 0.0 {
+        if discriminant > 0.0 {
             (unit - other * dot) * refractive_index - other * discriminant.sqrt()
         } else {
             Vector3::default()
-        };
+        }
     }
 }
 
@@ -1,25 +1,80 @@
-use crate::camera::Camera;
 use crate::hit::{Hit, HitRecord};
 use crate::ray::Ray;
 use crate::aabb::Aabb;
 use crate::aabb::surrounding_box;
+use crate::sampler::Sampler;
+use crate::vector::Vector3;
+use std::sync::Arc;
 
 pub struct World {
     pub hits: Vec<Box<dyn Hit>>,
+    // Objects that give off light, also in hits
+    pub lights: Vec<Arc<dyn Hit>>,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        World::new()
+    }
 }
 
 impl World {
     pub fn new() -> Self {
         World {
             hits: Vec::new(),
+            lights: Vec::new(),
         }
     }
 
+    // Objects are numbered from 1 in the order they are added
     pub fn add<H>(&mut self, hit: H)
     where
         H: Hit + 'static,
     {
-        self.hits.push(Box::new(hit));
+        let id = self.hits.len() as u32 + 1;
+        self.hits.push(Box::new(Object { id, hit }));
+    }
+
+    // Add an object with an emissive material, which paths will also sample directly
+    pub fn add_light<H>(&mut self, hit: H)
+    where
+        H: Hit + 'static,
+    {
+        let id = self.hits.len() as u32 + 1;
+        let light = Arc::new(Object { id, hit });
+        self.hits.push(Box::new(light.clone()));
+        self.lights.push(light);
+    }
+}
+
+// A primitive tagged with the object ID it reports in its hit records
+pub struct Object<H> {
+    pub id: u32,
+    pub hit: H,
+}
+
+impl<H: Hit> Hit for Object<H> {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let mut record = self.hit.hit(ray, t_min, t_max)?;
+        record.object_id = self.id;
+        Some(record)
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<Aabb> {
+        self.hit.bounding_box(t0, t1)
+    }
+
+    fn sample_direction(
+        &self,
+        origin: Vector3,
+        time: f32,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Vector3, f32)> {
+        self.hit.sample_direction(origin, time, sampler)
+    }
+
+    fn direction_pdf(&self, origin: Vector3, direction: Vector3, time: f32) -> f32 {
+        self.hit.direction_pdf(origin, direction, time)
     }
 }
 
@@ -39,10 +94,7 @@ impl Hit for World {
     }
 
     fn bounding_box(&self, t0: f32, t1: f32) -> Option<Aabb> {
-        let mut result: Option<Aabb> = None;
-        let mut temp = Aabb::default();
-
-        if self.hits.len() < 1 {
+        if self.hits.is_empty() {
             return None;
         }
 
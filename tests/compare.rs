@@ -0,0 +1,72 @@
+use pathtracer::compare::{compare, PIXELS_PER_DEGREE};
+use pathtracer::{Framebuffer, Rng, Vector3};
+
+// Smooth shading with a few hard edges, like a render
+fn scene(width: usize, height: usize) -> Framebuffer<Vector3> {
+    let pixels = (0..width * height)
+        .map(|i| {
+            let (x, y) = ((i % width) as f32, (i / width) as f32);
+            let disc = (x - 20.0).hypot(y - 16.0) < 9.0;
+            match disc {
+                true => Vector3::new(0.8, 0.3, 0.1),
+                false => Vector3::new(x / width as f32, 0.5, y / height as f32),
+            }
+        })
+        .collect();
+
+    Framebuffer::from_pixels(width, height, pixels)
+}
+
+// The scene with noise of the given strength added to every channel
+fn noisy(image: &Framebuffer<Vector3>, strength: f32, rng: &mut Rng) -> Framebuffer<Vector3> {
+    let pixels = image
+        .pixels()
+        .iter()
+        .map(|&p| {
+            let noise = Vector3::new(rng.float(), rng.float(), rng.float());
+            p + (noise - Vector3::new(0.5, 0.5, 0.5)) * strength
+        })
+        .collect();
+
+    Framebuffer::from_pixels(image.width(), image.height(), pixels)
+}
+
+#[test]
+fn identical_images_have_no_error() {
+    let image = scene(48, 32);
+    let comparison = compare(&image, &image, PIXELS_PER_DEGREE);
+
+    assert_eq!(comparison.mse, 0.0);
+    assert_eq!(comparison.rel_mse, 0.0);
+    assert_eq!(comparison.psnr, f32::INFINITY);
+    assert!((comparison.ssim - 1.0).abs() < 1e-5);
+    assert_eq!(comparison.flip, 0.0);
+    assert!(comparison.to_json().contains("\"psnr\": null"));
+}
+
+#[test]
+fn errors_grow_with_noise() {
+    let reference = scene(48, 32);
+    let mut rng = Rng::new(3);
+    let slight = compare(
+        &noisy(&reference, 0.05, &mut rng),
+        &reference,
+        PIXELS_PER_DEGREE,
+    );
+    let strong = compare(
+        &noisy(&reference, 0.4, &mut rng),
+        &reference,
+        PIXELS_PER_DEGREE,
+    );
+
+    assert!(slight.mse > 0.0 && slight.mse < strong.mse);
+    assert!(slight.rel_mse < strong.rel_mse);
+    assert!(slight.psnr > strong.psnr);
+    assert!(slight.ssim < 1.0 && slight.ssim > strong.ssim);
+    assert!(slight.flip > 0.0 && slight.flip < strong.flip);
+    assert!(strong
+        .flip_map
+        .pixels()
+        .iter()
+        .all(|e| (0.0..=1.0).contains(e)));
+}
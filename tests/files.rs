@@ -0,0 +1,255 @@
+use pathtracer::checkpoint::{read_checkpoint, write_checkpoint, Checkpoint};
+use pathtracer::environment::MapFile;
+use pathtracer::image::{
+    read_exr_channels, read_hdr, read_pfm, read_png, read_ppm, write_exr_channels, write_hdr,
+    write_pfm, write_png, write_ppm, Channel,
+};
+use pathtracer::pipeline::{render_image, Crop, OutputOptions, RenderOptions};
+use pathtracer::render::{Accumulator, RenderSettings, Tile};
+use pathtracer::scenes::find_scene;
+use pathtracer::{
+    Filter, FilterKind, Framebuffer, ImageFormat, MisHeuristic, SamplerKind, Tonemap, Vector3,
+};
+use std::fs;
+use std::time::Duration;
+
+// A small image with a different value in every channel of every pixel
+fn gradient(width: usize, height: usize) -> Framebuffer<Vector3> {
+    let pixels = (0..width * height)
+        .map(|i| Vector3::new(i as f32, 0.5 * i as f32, -(i as f32) / 3.0))
+        .collect();
+
+    Framebuffer::from_pixels(width, height, pixels)
+}
+
+#[test]
+fn pfm_round_trip() {
+    let image = gradient(5, 3);
+
+    let mut bytes = Vec::new();
+    write_pfm(&mut bytes, &image).unwrap();
+
+    assert_eq!(read_pfm(&bytes[..]).unwrap(), image);
+}
+
+#[test]
+fn ppm_and_png_round_trip() {
+    let image = gradient(4, 3).map(|p| Vector3::new(p.x / 11.0, p.y / 11.0, 1.0 + p.z / 11.0));
+    let quantized = image.to_rgb8();
+    let expected = quantized.map(|p| Vector3::new(p[0] as f32, p[1] as f32, p[2] as f32) / 255.0);
+
+    let mut ppm = Vec::new();
+    write_ppm(&mut ppm, &quantized).unwrap();
+    assert_eq!(read_ppm(&ppm[..]).unwrap(), expected);
+
+    let mut png = Vec::new();
+    write_png(&mut png, &quantized).unwrap();
+    assert_eq!(read_png(&png[..]).unwrap(), expected);
+}
+
+#[test]
+fn ascii_ppm_files_with_comments_are_read() {
+    let ppm = b"P3\n# written by hand\n2 1 # width and height\n15\n15 0 5\n  0 15 3\n";
+    let image = read_ppm(&ppm[..]).unwrap();
+
+    assert_eq!((image.width(), image.height()), (2, 1));
+    assert_eq!(image.get(0, 0), Vector3::new(1.0, 0.0, 5.0 / 15.0));
+    assert_eq!(image.get(1, 0), Vector3::new(0.0, 1.0, 3.0 / 15.0));
+
+    // Values past the maximum are rejected
+    assert!(read_ppm(&b"P3 1 1 15 16 0 0"[..]).is_err());
+}
+
+#[test]
+fn hdr_round_trip_and_run_length_decoding() {
+    let image = gradient(5, 3).map(|p| Vector3::new(p.x, p.y, 0.25));
+
+    let mut bytes = Vec::new();
+    write_hdr(&mut bytes, &image).unwrap();
+    let read = read_hdr(&bytes[..]).unwrap();
+
+    // Eight bit mantissas keep about two significant digits
+    for (a, b) in read.pixels().iter().zip(image.pixels()) {
+        assert!((*a - *b).length() <= 0.01 * b.length());
+    }
+
+    // One scanline of eight pixels, every component a single run
+    let mut encoded = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 8\n".to_vec();
+    encoded.extend_from_slice(&[2, 2, 0, 8]);
+    for &value in &[128, 64, 0, 129] {
+        encoded.extend_from_slice(&[128 + 8, value]);
+    }
+    let read = read_hdr(&encoded[..]).unwrap();
+    assert_eq!(read.pixels(), &[Vector3::new(1.0, 0.5, 0.0); 8][..]);
+}
+
+#[test]
+fn exr_round_trip_keeps_every_layer() {
+    let image = gradient(4, 6);
+    let mut channels = Channel::split("", ["R", "G", "B"], &image);
+    channels.push(Channel {
+        name: "depth.Z".to_string(),
+        values: image.map(|p| p.x * 2.0),
+    });
+
+    let mut bytes = Vec::new();
+    write_exr_channels(&mut bytes, &channels).unwrap();
+    let mut read = read_exr_channels(&bytes[..]).unwrap();
+
+    // Channels are stored in alphabetical order
+    read.sort_by(|a, b| a.name.cmp(&b.name));
+    channels.sort_by(|a, b| a.name.cmp(&b.name));
+    assert_eq!(read, channels);
+}
+
+#[test]
+fn checkpoint_round_trip() {
+    let mut accumulator = Accumulator::new(3, 2);
+    accumulator.sum = gradient(3, 2);
+    accumulator.weights = accumulator.sum.map(|p| p.y + 1.0);
+    accumulator.abs_weights = accumulator.sum.map(|p| p.y + 2.0);
+    accumulator.samples = accumulator.sum.map(|p| p.x as u32);
+
+    let checkpoint = Checkpoint {
+        scene: "basic".to_string(),
+        seed: 42,
+        max_depth: 12,
+        min_bounces: 2,
+        filter: Filter {
+            kind: FilterKind::Gaussian,
+            radius: 1.25,
+        },
+        shutter: (0.5, 0.75),
+        sampler: SamplerKind::Sobol,
+        mis: MisHeuristic::Balance,
+        environment: Some(MapFile {
+            path: "sky.hdr".to_string(),
+            rotation: 90.0,
+            intensity: 2.5,
+        }),
+        crop: Some(Tile {
+            x0: 1,
+            y0: 0,
+            x1: 3,
+            y1: 2,
+        }),
+        accumulator,
+    };
+
+    let mut bytes = Vec::new();
+    write_checkpoint(&mut bytes, &checkpoint).unwrap();
+
+    assert_eq!(read_checkpoint(&bytes[..]).unwrap(), checkpoint);
+}
+
+#[test]
+fn truncated_checkpoints_are_rejected() {
+    let checkpoint = Checkpoint {
+        scene: "basic".to_string(),
+        seed: 0,
+        max_depth: 50,
+        min_bounces: 3,
+        filter: Filter::default(),
+        shutter: (0.0, 1.0),
+        sampler: SamplerKind::Independent,
+        mis: MisHeuristic::Power,
+        environment: None,
+        crop: None,
+        accumulator: Accumulator::new(4, 4),
+    };
+
+    let mut bytes = Vec::new();
+    write_checkpoint(&mut bytes, &checkpoint).unwrap();
+    let mut truncated = bytes.clone();
+    truncated.truncate(bytes.len() - 1);
+    assert!(read_checkpoint(&truncated[..]).is_err());
+
+    // Headers claiming a huge image or scene name fail before allocating for them
+    let mut huge = bytes.clone();
+    huge[8..16].copy_from_slice(&[0xff; 8]);
+    assert!(read_checkpoint(&huge[..]).is_err());
+
+    let mut huge = bytes;
+    huge[32..36].copy_from_slice(&[0xff; 4]);
+    assert!(read_checkpoint(&huge[..]).is_err());
+}
+
+#[test]
+fn resumed_renders_keep_their_size_and_crop() {
+    let dir = std::env::temp_dir().join(format!("pathtracer-resume-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+
+    let options = RenderOptions {
+        scene: find_scene("basic").unwrap(),
+        crop: Some(Crop::Window([0.25, 0.25, 0.75, 0.75])),
+        shutter: (0.0, 1.0),
+        environment: None,
+        pass_samples: Some(2),
+        adaptive: None,
+        min_samples: 16,
+        checkpoint: Some(path("render.ptck")),
+        checkpoint_interval: Duration::from_secs(60),
+        resume: None,
+    };
+    let output = OutputOptions {
+        path: path("render.pfm"),
+        format: ImageFormat::Pfm,
+        tonemap: Tonemap::default(),
+        crop_output: false,
+        aovs: Vec::new(),
+        aov_layers: Vec::new(),
+        denoise: false,
+        heatmap: None,
+        report: false,
+    };
+    render_image(
+        RenderSettings::new(16, 12, 2),
+        options.clone(),
+        &output,
+        |_| {},
+    )
+    .unwrap();
+
+    // The size and crop come from the checkpoint, not the settings
+    let resumed = RenderOptions {
+        crop: None,
+        checkpoint: None,
+        resume: Some(path("render.ptck")),
+        ..options.clone()
+    };
+    let stats = render_image(
+        RenderSettings::new(8, 8, 4),
+        resumed.clone(),
+        &output,
+        |_| {},
+    )
+    .unwrap();
+    assert_eq!((stats.width, stats.height), (16, 12));
+
+    let saved = pathtracer::checkpoint::load(path("render.ptck")).unwrap();
+    let crop = Tile {
+        x0: 4,
+        y0: 3,
+        x1: 12,
+        y1: 9,
+    };
+    assert_eq!(saved.crop, Some(crop));
+    assert_eq!(saved.accumulator.samples.get(4, 3), 4);
+    assert_eq!(saved.accumulator.samples.get(0, 0), 0);
+
+    // Only the same region may be asked for again
+    let same = RenderOptions {
+        crop: Some(Crop::Pixels([4, 3, 12, 9])),
+        ..resumed.clone()
+    };
+    assert!(render_image(RenderSettings::new(8, 8, 6), same, &output, |_| {}).is_ok());
+
+    let other = RenderOptions {
+        crop: Some(Crop::Pixels([0, 0, 8, 6])),
+        ..resumed
+    };
+    assert!(render_image(RenderSettings::new(8, 8, 8), other, &output, |_| {}).is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,120 @@
+// Renders every registered scene at low resolution and compares it with the reference
+// image stored in tests/golden. After an intended change to the output, rewrite the
+// references with
+//
+//     UPDATE_GOLDENS=1 cargo test --test golden
+//
+// and check the new images before committing them
+use pathtracer::compare::{compare, Comparison, PIXELS_PER_DEGREE};
+use pathtracer::image::{self, ImageFormat};
+use pathtracer::scenes::{Scene, SCENES};
+use pathtracer::{render, Framebuffer, RenderSettings, Rng, Tonemap, Vector3};
+use std::env;
+use std::path::PathBuf;
+
+// How a scene is rendered and judged
+// Renders are deterministic, but floating point differences between platforms can send
+// paths elsewhere, so the tolerances sit above the error of a render with another seed.
+// FLIP also stays below that of the image darkened by 5%, relMSE only catches larger
+// changes
+struct Tolerance {
+    samples: usize,
+    // Clamp both images to this before comparing, so the odd firefly landing elsewhere
+    // is not mistaken for a change
+    clamp: Option<f32>,
+    max_rel_mse: f32,
+    max_flip: f32,
+}
+
+const DEFAULT: Tolerance = Tolerance {
+    samples: 32,
+    clamp: None,
+    max_rel_mse: 0.02,
+    max_flip: 0.08,
+};
+
+// Small bright lights over a black sky leave the dark pixels noisy for many samples, and
+// their relative error blows up unless the lights are clamped to the display range
+const EMITTERS: Tolerance = Tolerance {
+    samples: 512,
+    clamp: Some(1.0),
+    max_rel_mse: 0.02,
+    max_flip: 0.04,
+};
+
+fn tolerance(scene: &Scene) -> &'static Tolerance {
+    match scene.name {
+        "lamps" => &EMITTERS,
+        _ => &DEFAULT,
+    }
+}
+
+fn golden_path(scene: &Scene) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "golden"]
+        .iter()
+        .collect::<PathBuf>()
+        .join(format!("{}.pfm", scene.name))
+}
+
+fn render_scene(scene: &Scene) -> Framebuffer<Vector3> {
+    let settings = RenderSettings {
+        tile_size: 16,
+        ..RenderSettings::new(64, 48, tolerance(scene).samples)
+    };
+    let (world, camera, lights) = (scene.build)(
+        settings.width,
+        settings.height,
+        (0.0, 1.0),
+        &mut Rng::new(settings.seed),
+    );
+
+    render(world.as_ref(), &lights, &camera, &settings)
+}
+
+fn check(scene: &Scene) -> Result<Comparison, String> {
+    let path = golden_path(scene);
+    let rendered = render_scene(scene);
+
+    if env::var_os("UPDATE_GOLDENS").is_some() {
+        image::save(&path, &rendered, ImageFormat::Pfm, &Tonemap::default())
+            .map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+    }
+
+    let golden =
+        image::load(&path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    if (golden.width(), golden.height()) != (rendered.width(), rendered.height()) {
+        return Err(format!("{} has the wrong size", path.display()));
+    }
+
+    let clamp = |mut image: Framebuffer<Vector3>| {
+        if let Some(max) = tolerance(scene).clamp {
+            for p in image.pixels_mut() {
+                *p = Vector3::new(p.x.min(max), p.y.min(max), p.z.min(max));
+            }
+        }
+        image
+    };
+
+    Ok(compare(&clamp(rendered), &clamp(golden), PIXELS_PER_DEGREE))
+}
+
+#[test]
+fn scenes_match_their_golden_images() {
+    let mut failures = Vec::new();
+
+    for scene in SCENES {
+        let tolerance = tolerance(scene);
+        match check(scene) {
+            Ok(c) if c.rel_mse <= tolerance.max_rel_mse && c.flip <= tolerance.max_flip => {}
+            Ok(c) => failures.push(format!("{}:\n{}", scene.name, c.summary())),
+            Err(e) => failures.push(format!("{}: {}", scene.name, e)),
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "renders differ from tests/golden, rerun with UPDATE_GOLDENS=1 if the change is \
+         intended\n{}",
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,141 @@
+use pathtracer::{
+    render, Camera, Constant, Dielectric, Lambertian, Lights, Lobes, Material, Metal,
+    RenderSettings, SamplerKind, Sphere, Vector3, World,
+};
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+fn local(theta: f32, phi: f32) -> Vector3 {
+    Vector3::new(
+        theta.sin() * phi.cos(),
+        theta.sin() * phi.sin(),
+        theta.cos(),
+    )
+}
+
+// Pinhole camera looking from one point at another, its shutter open from 0 to 1
+fn camera(from: Vector3, at: Vector3, vfov: f32, settings: &RenderSettings) -> Camera {
+    let aspect = settings.width as f32 / settings.height as f32;
+    Camera::new(
+        from,
+        at,
+        Vector3::unit_y(),
+        vfov,
+        aspect,
+        0.0,
+        1.0,
+        0.0,
+        1.0,
+    )
+}
+
+#[test]
+fn samples_match_eval_and_pdf() {
+    let materials: [(&str, Box<dyn Material>); 2] = [
+        (
+            "lambertian",
+            Box::new(Lambertian::new(Vector3::new(0.8, 0.5, 0.2))),
+        ),
+        (
+            "metal",
+            Box::new(Metal::new(Vector3::new(0.9, 0.9, 0.9), 0.3)),
+        ),
+    ];
+    let wo = local(0.6, 0.4);
+
+    for (name, material) in &materials {
+        assert!(!material.lobes().is_delta(), "{}", name);
+
+        let mut sampler = SamplerKind::Independent.create(0, 4096);
+        let mut estimate = 0.0;
+        for index in 0..4096 {
+            sampler.start_sample((0, 0), index);
+            let sample = match material.sample(wo, sampler.as_mut()) {
+                Some(sample) => sample,
+                None => continue,
+            };
+
+            let wi = sample.direction;
+            assert!((wi.length() - 1.0).abs() < 1e-4, "{}", name);
+            let pdf = material.pdf(wo, wi);
+            assert!((pdf - sample.pdf).abs() <= 1e-3 * pdf, "{}", name);
+            let weight = material.eval(wo, wi) / pdf;
+            assert!((weight - sample.weight).length() < 1e-3, "{}", name);
+
+            // Total probability of picking a direction the material gives weight to
+            estimate += 1.0 / 4096.0;
+        }
+
+        // The density integrates to the chance of not being absorbed over the sphere
+        let (steps, mut integral) = (256, 0.0);
+        for i in 0..steps {
+            let theta = PI * (i as f32 + 0.5) / steps as f32;
+            for j in 0..steps {
+                let phi = 2.0 * PI * (j as f32 + 0.5) / steps as f32;
+                let wi = local(theta, phi);
+                if material.eval(wo, wi) != Vector3::default() {
+                    integral +=
+                        material.pdf(wo, wi) * theta.sin() * 2.0 * PI * PI / (steps * steps) as f32;
+                }
+            }
+        }
+        assert!(
+            (estimate - integral).abs() < 0.03,
+            "{} {} {}",
+            name,
+            estimate,
+            integral
+        );
+    }
+}
+
+#[test]
+fn exact_directions_have_no_density() {
+    let mirror = Metal::new(Vector3::new(0.9, 0.9, 0.9), 0.0);
+    let glass = Dielectric::new(1.5);
+    let wo = local(0.3, 1.0);
+    let mut sampler = SamplerKind::Independent.create(0, 16);
+
+    for material in &[&mirror as &dyn Material, &glass] {
+        assert!(material.lobes().contains(Lobes::DELTA));
+        sampler.start_sample((0, 0), 0);
+        let sample = material.sample(wo, sampler.as_mut()).unwrap();
+        assert!(sample.lobe.contains(Lobes::DELTA));
+        assert_eq!(material.pdf(wo, sample.direction), 0.0);
+        assert_eq!(material.eval(wo, sample.direction), Vector3::default());
+    }
+
+    // The mirror reflects about the normal
+    sampler.start_sample((0, 0), 0);
+    let reflected = mirror.sample(wo, sampler.as_mut()).unwrap().direction;
+    assert!((reflected - Vector3::new(-wo.x, -wo.y, wo.z)).length() < 1e-6);
+}
+
+#[test]
+fn white_diffuse_surfaces_in_a_white_sky_vanish() {
+    // A white sphere under light equal from everywhere has the sky's radiance wherever
+    // it is seen, if its scattering keeps all the light
+    let settings = RenderSettings {
+        max_depth: 200,
+        ..RenderSettings::new(16, 16, 64)
+    };
+    let camera = camera(
+        Vector3::new(0.0, 0.0, 3.0),
+        Vector3::default(),
+        40.0,
+        &settings,
+    );
+
+    let mut world = World::new();
+    world.add(Sphere::new(
+        Vector3::default(),
+        1.0,
+        Arc::new(Lambertian::new(Vector3::new(1.0, 1.0, 1.0))),
+    ));
+    let lights = Lights::new(Box::new(Constant::new(Vector3::new(0.5, 0.5, 0.5))));
+
+    let image = render(&world, &lights, &camera, &settings);
+    for p in image.pixels() {
+        assert!((p.x - 0.5).abs() < 1e-3, "{:?}", p);
+    }
+}
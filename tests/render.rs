@@ -0,0 +1,563 @@
+use pathtracer::adaptive::{
+    next_budget, pixel_error, render_adaptive, sample_heatmap, AdaptiveSettings,
+};
+use pathtracer::render::{render_pass, render_progressive, Accumulator, Tile, TileOrder};
+use pathtracer::scenes::find_scene;
+use pathtracer::{
+    render, Animated, BvhNode, Camera, Constant, DiffuseLight, Environment, EnvironmentMap, Filter,
+    FilterKind, Framebuffer, Gradient, Hit, Keyframes, Lambertian, Lights, Metal, MisHeuristic,
+    MovingSphere, Ray, RenderSettings, Rng, SamplerKind, Sphere, Vector3, World,
+};
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+fn small_settings(threads: usize) -> RenderSettings {
+    RenderSettings {
+        threads,
+        tile_size: 8,
+        ..RenderSettings::new(24, 16, 4)
+    }
+}
+
+fn render_scene(name: &str, settings: &RenderSettings) -> Framebuffer<Vector3> {
+    let scene = find_scene(name).unwrap();
+    let (world, camera, lights) = (scene.build)(
+        settings.width,
+        settings.height,
+        (0.0, 1.0),
+        &mut Rng::new(0),
+    );
+    render(world.as_ref(), &lights, &camera, settings)
+}
+
+// Pinhole camera looking from one point at another, its shutter open from 0 to 1
+fn camera(from: Vector3, at: Vector3, vfov: f32, settings: &RenderSettings) -> Camera {
+    let aspect = settings.width as f32 / settings.height as f32;
+    Camera::new(
+        from,
+        at,
+        Vector3::unit_y(),
+        vfov,
+        aspect,
+        0.0,
+        1.0,
+        0.0,
+        1.0,
+    )
+}
+
+#[test]
+fn renders_do_not_depend_on_the_thread_count() {
+    let one = render_scene("basic", &small_settings(1));
+    let four = render_scene("basic", &small_settings(4));
+
+    assert_eq!(one, four);
+}
+
+#[test]
+fn renders_are_reproducible_from_the_seed() {
+    let settings = small_settings(2);
+    let first = render_scene("random", &settings);
+    let second = render_scene("random", &settings);
+    assert_eq!(first, second);
+
+    let reseeded = render_scene(
+        "random",
+        &RenderSettings {
+            seed: 1,
+            ..settings
+        },
+    );
+    assert_ne!(first, reseeded);
+}
+
+#[test]
+fn tile_orders_render_the_same_image() {
+    let scanline = render_scene("basic", &small_settings(2));
+
+    for &tile_order in &[TileOrder::Spiral, TileOrder::Hilbert] {
+        let settings = RenderSettings {
+            tile_order,
+            ..small_settings(2)
+        };
+        assert_eq!(render_scene("basic", &settings), scanline);
+    }
+}
+
+#[test]
+fn crops_only_render_their_region() {
+    let crop = Tile {
+        x0: 5,
+        y0: 3,
+        x1: 19,
+        y1: 11,
+    };
+    let full = render_scene("basic", &small_settings(2));
+    let cropped = render_scene(
+        "basic",
+        &RenderSettings {
+            crop: Some(crop),
+            ..small_settings(2)
+        },
+    );
+
+    for y in 0..full.height() {
+        for x in 0..full.width() {
+            let inside = (crop.x0..crop.x1).contains(&x) && (crop.y0..crop.y1).contains(&y);
+            if inside {
+                assert_eq!(cropped.get(x, y), full.get(x, y));
+            } else {
+                assert_eq!(cropped.get(x, y), Vector3::default());
+            }
+        }
+    }
+}
+
+#[test]
+fn negative_lobes_never_make_pixels_negative() {
+    // Few samples under a narrow filter around bright lamps, where the weights of a
+    // pixel can all but cancel out
+    for &(kind, radius) in &[(FilterKind::Mitchell, 0.6), (FilterKind::Lanczos, 3.0)] {
+        let settings = RenderSettings {
+            samples: 1,
+            filter: Filter { kind, radius },
+            ..small_settings(2)
+        };
+        let image = render_scene("lamps", &settings);
+
+        // The brightest lamp gives off 40, ringing may overshoot it but not by much
+        for p in image.pixels() {
+            assert!(p.x >= 0.0 && p.y >= 0.0 && p.z >= 0.0);
+            assert!(p.x.max(p.y).max(p.z) <= 4.0 * 40.0);
+        }
+    }
+}
+
+#[test]
+fn progressive_passes_add_up_to_a_single_pass() {
+    let settings = small_settings(2);
+    let scene = find_scene("basic").unwrap();
+    let (world, camera, lights) = (scene.build)(
+        settings.width,
+        settings.height,
+        (0.0, 1.0),
+        &mut Rng::new(0),
+    );
+
+    let mut single = Accumulator::new(settings.width, settings.height);
+    render_pass(world.as_ref(), &lights, &camera, &settings, &mut single, 4);
+
+    let mut passes = 0;
+    let mut progressive = Accumulator::new(settings.width, settings.height);
+    render_progressive(
+        world.as_ref(),
+        &lights,
+        &camera,
+        &settings,
+        &mut progressive,
+        1,
+        |_| -> Result<(), ()> {
+            passes += 1;
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    assert_eq!(passes, 4);
+    assert_eq!(progressive.samples, single.samples);
+
+    // Passes only change the order samples are summed in
+    let single = single.resolve();
+    let progressive = progressive.resolve();
+    for (a, b) in single.pixels().iter().zip(progressive.pixels()) {
+        assert!((*a - *b).length() <= 1e-4 * (1.0 + a.length()));
+    }
+}
+
+#[test]
+fn an_empty_world_shows_the_sky() {
+    let settings = small_settings(1);
+    let camera = camera(
+        Vector3::default(),
+        Vector3::new(0.0, 0.0, -1.0),
+        90.0,
+        &settings,
+    );
+
+    let sky = Lights::new(Box::new(Gradient::sky()));
+    let image = render(&World::new(), &sky, &camera, &settings);
+
+    // The sky fades from white at the horizon to blue overhead
+    let top = image.get(12, 0);
+    let bottom = image.get(12, 15);
+    assert!(top.z > 0.99 && bottom.z > 0.99);
+    assert!(top.x < bottom.x);
+}
+
+#[test]
+fn lights_shine_from_the_side_they_face() {
+    let settings = small_settings(1);
+    let camera = camera(
+        Vector3::default(),
+        Vector3::new(0.0, 0.0, -1.0),
+        90.0,
+        &settings,
+    );
+    let black = Lights::new(Box::new(Constant::new(Vector3::default())));
+    let color = Vector3::new(1.0, 0.5, 0.25);
+
+    // The camera sits inside a sphere whose normals point away from it
+    let inside = |light: DiffuseLight| {
+        let mut world = World::new();
+        world.add(Sphere::new(Vector3::default(), 10.0, Arc::new(light)));
+        render(&world, &black, &camera, &settings)
+    };
+
+    let one_sided = inside(DiffuseLight::new(color, 4.0));
+    assert!(one_sided.pixels().iter().all(|&p| p == Vector3::default()));
+
+    let two_sided = inside(DiffuseLight::two_sided(color, 4.0));
+    assert!(two_sided
+        .pixels()
+        .iter()
+        .all(|&p| (p - color * 4.0).length() < 1e-4));
+}
+
+#[test]
+fn environment_maps_put_their_middle_ahead() {
+    let red = Vector3::new(1.0, 0.0, 0.0);
+    let green = Vector3::new(0.0, 1.0, 0.0);
+    let blue = Vector3::new(0.0, 0.0, 1.0);
+    let image = Framebuffer::from_pixels(3, 1, vec![red, green, blue]);
+    let ahead = -Vector3::unit_z();
+
+    let map = EnvironmentMap::new(image.clone(), 0.0, 2.0);
+    assert_eq!(map.radiance(ahead), green * 2.0);
+    // Behind lies the seam, where both ends of the image meet
+    assert_eq!(map.radiance(Vector3::unit_z()), (red + blue) * 2.0 / 2.0);
+
+    // A third of a turn brings the first column ahead
+    let turned = EnvironmentMap::new(image, 120.0, 1.0);
+    assert!((turned.radiance(ahead) - red).length() < 1e-4);
+}
+
+#[test]
+fn environment_map_samples_match_their_density() {
+    // A dim sky with one bright pixel
+    let mut image = Framebuffer::from_pixels(16, 8, vec![Vector3::new(0.1, 0.1, 0.1); 128]);
+    image.set(11, 2, Vector3::new(50.0, 50.0, 50.0));
+    let map = EnvironmentMap::new(image, 30.0, 1.0);
+
+    let mut sampler = SamplerKind::Independent.create(0, 4096);
+    let mut towards_sun = 0;
+    let mut estimate = Vector3::default();
+    for index in 0..4096 {
+        sampler.start_sample((0, 0), index);
+        let (direction, radiance, pdf) = map.sample(sampler.as_mut()).unwrap();
+
+        assert!((direction.length() - 1.0).abs() < 1e-4);
+        assert!((map.pdf(direction) - pdf).abs() <= 1e-3 * pdf);
+        assert_eq!(map.radiance(direction), radiance);
+
+        towards_sun += (radiance.x > 10.0) as u32;
+        estimate += radiance / pdf / 4096.0;
+    }
+
+    // The bright pixel gives most of the light and is picked most of the time, and the
+    // samples add up to the light arriving from all around
+    assert!(towards_sun > 2048);
+    let (width, height) = (512, 256);
+    let mut total = 0.0;
+    for y in 0..height {
+        let theta = PI * (y as f32 + 0.5) / height as f32;
+        for x in 0..width {
+            let phi = 2.0 * PI * (x as f32 + 0.5) / width as f32;
+            let direction = Vector3::new(
+                theta.sin() * phi.cos(),
+                theta.cos(),
+                theta.sin() * phi.sin(),
+            );
+            total +=
+                map.radiance(direction).x * theta.sin() * 2.0 * PI * PI / (width * height) as f32;
+        }
+    }
+    assert!((estimate.x - total).abs() < 0.02 * total);
+}
+
+#[test]
+fn animated_objects_follow_their_keyframes() {
+    let sphere = Sphere::new(
+        Vector3::default(),
+        0.5,
+        Arc::new(Lambertian::new(Vector3::new(0.5, 0.5, 0.5))),
+    );
+    let path = Keyframes::new(vec![
+        (0.0, Vector3::new(-2.0, 0.0, 0.0)),
+        (1.0, Vector3::new(2.0, 0.0, 0.0)),
+    ]);
+    let animated = Animated::new(sphere, path);
+
+    // A ray straight down the z axis only meets the sphere halfway along its path
+    let ray_at = |time| Ray::with_time(Vector3::new(0.0, 0.0, 5.0), -Vector3::unit_z(), time);
+    assert!(animated.hit(ray_at(0.0), 0.001, f32::MAX).is_none());
+    assert!(animated.hit(ray_at(1.0), 0.001, f32::MAX).is_none());
+
+    let record = animated.hit(ray_at(0.5), 0.001, f32::MAX).unwrap();
+    assert!((record.p - Vector3::new(0.0, 0.0, 0.5)).length() < 1e-5);
+
+    let bounds = animated.bounding_box(0.0, 1.0).unwrap();
+    assert_eq!(bounds.min.x, -2.5);
+    assert_eq!(bounds.max.x, 2.5);
+}
+
+#[test]
+#[should_panic(expected = "a BVH needs at least one object")]
+fn a_bvh_of_nothing_is_refused() {
+    BvhNode::new(Vec::new(), 0.0, 1.0, &mut Rng::new(0));
+}
+
+#[test]
+fn sphere_samples_match_their_density() {
+    let light = Arc::new(DiffuseLight::new(Vector3::new(1.0, 1.0, 1.0), 1.0));
+    let moving = MovingSphere::new(
+        Vector3::new(0.0, 0.0, -3.0),
+        Vector3::new(0.0, 4.0, -3.0),
+        0.0,
+        1.0,
+        1.0,
+        light,
+    );
+
+    // From outside, at a time the sphere is partway along, and from inside it
+    let cases = [
+        (Vector3::default(), 0.5),
+        (Vector3::new(0.2, 2.5, -3.0), 0.75),
+    ];
+    let mut sampler = SamplerKind::Independent.create(0, 4096);
+    for &(origin, time) in &cases {
+        let mut solid_angle = 0.0;
+        for index in 0..4096 {
+            sampler.start_sample((0, 0), index);
+            let (direction, pdf) = moving
+                .sample_direction(origin, time, sampler.as_mut())
+                .unwrap();
+
+            assert!((direction.length() - 1.0).abs() < 1e-4);
+            assert!((moving.direction_pdf(origin, direction, time) - pdf).abs() <= 1e-3 * pdf);
+            let ray = Ray::with_time(origin, direction, time);
+            assert!(moving.hit(ray, 0.0001, f32::MAX).is_some());
+
+            solid_angle += 1.0 / pdf / 4096.0;
+        }
+
+        // The samples cover the cone the sphere fills, or every direction from inside
+        let center = Vector3::new(0.0, 4.0 * time, -3.0);
+        let distance = (center - origin).length();
+        let expected = if distance > 1.0 {
+            2.0 * PI * (1.0 - (1.0 - 1.0 / (distance * distance)).sqrt())
+        } else {
+            4.0 * PI
+        };
+        assert!((solid_angle - expected).abs() < 0.05 * expected);
+    }
+
+    // Directions missing the sphere are never picked
+    let away = Vector3::new(0.0, 0.0, 1.0);
+    assert_eq!(moving.direction_pdf(Vector3::default(), away, 0.5), 0.0);
+}
+
+#[test]
+fn sampling_lights_directly_agrees_with_finding_them() {
+    let settings = RenderSettings {
+        samples: 256,
+        ..small_settings(2)
+    };
+    let camera = camera(
+        Vector3::new(0.0, 1.0, 3.0),
+        Vector3::default(),
+        60.0,
+        &settings,
+    );
+
+    let mut world = World::new();
+    world.add(Sphere::new(
+        Vector3::new(0.0, -100.0, 0.0),
+        100.0,
+        Arc::new(Lambertian::new(Vector3::new(0.5, 0.5, 0.5))),
+    ));
+    world.add_light(Sphere::new(
+        Vector3::new(0.5, 1.5, 0.0),
+        0.5,
+        Arc::new(DiffuseLight::new(Vector3::new(1.0, 0.9, 0.8), 4.0)),
+    ));
+
+    // The same world, with the lamp only found by paths scattering into it
+    let black = || Box::new(Constant::new(Vector3::default()));
+    let sampled = Lights {
+        environment: black(),
+        emitters: world.lights.clone(),
+    };
+    let found = Lights::new(black());
+
+    let mean = |lights: &Lights| {
+        let image = render(&world, lights, &camera, &settings);
+        image.pixels().iter().map(|p| p.x + p.y + p.z).sum::<f32>() / image.pixels().len() as f32
+    };
+    let (sampled, found) = (mean(&sampled), mean(&found));
+    assert!(sampled > 0.0);
+    assert!((sampled - found).abs() < 0.03 * found);
+}
+
+#[test]
+fn mis_heuristics_agree_on_a_lamp_in_a_glossy_floor() {
+    let settings = small_settings(2);
+    let camera = camera(
+        Vector3::new(0.0, 1.0, 3.0),
+        Vector3::default(),
+        60.0,
+        &settings,
+    );
+
+    // Sampling the small lamp rarely lands in the floor's narrow reflection, which
+    // scattering finds easily
+    let mut world = World::new();
+    world.add(Sphere::new(
+        Vector3::new(0.0, -100.0, 0.0),
+        100.0,
+        Arc::new(Metal::new(Vector3::new(0.8, 0.8, 0.8), 0.05)),
+    ));
+    world.add_light(Sphere::new(
+        Vector3::new(0.0, 0.6, -1.0),
+        0.1,
+        Arc::new(DiffuseLight::new(Vector3::new(1.0, 1.0, 1.0), 20.0)),
+    ));
+    let lights = Lights {
+        environment: Box::new(Constant::new(Vector3::new(0.1, 0.1, 0.1))),
+        emitters: world.lights.clone(),
+    };
+
+    let mean = |mis| {
+        let settings = RenderSettings {
+            samples: 256,
+            mis,
+            ..settings
+        };
+        let image = render(&world, &lights, &camera, &settings);
+        image.pixels().iter().map(|p| p.x + p.y + p.z).sum::<f32>() / image.pixels().len() as f32
+    };
+    let (balance, power) = (mean(MisHeuristic::Balance), mean(MisHeuristic::Power));
+    assert!((balance - power).abs() < 0.03 * power);
+}
+
+#[test]
+fn adaptive_sampling_spends_samples_where_the_noise_is() {
+    let settings = small_settings(2);
+    let camera = camera(
+        Vector3::default(),
+        Vector3::new(0.0, 0.0, -1.0),
+        90.0,
+        &settings,
+    );
+
+    // The top rows only see the constant sky, below them a ball casts a soft contact
+    // shadow on the ground
+    let mut world = World::new();
+    world.add(Sphere::new(
+        Vector3::new(0.0, -100.5, -1.0),
+        100.0,
+        Arc::new(Lambertian::new(Vector3::new(0.5, 0.5, 0.5))),
+    ));
+    world.add(Sphere::new(
+        Vector3::new(0.0, 0.0, -1.0),
+        0.5,
+        Arc::new(Lambertian::new(Vector3::new(0.5, 0.5, 0.5))),
+    ));
+    let sky = Lights::new(Box::new(Constant::new(Vector3::new(0.5, 0.7, 1.0))));
+
+    let adaptive = AdaptiveSettings {
+        threshold: 0.01,
+        min_samples: 16,
+        max_samples: 100,
+        pass_samples: 24,
+    };
+    let mut accumulator = Accumulator::new(settings.width, settings.height);
+    let mut passes = 0;
+    render_adaptive::<_, ()>(
+        &world,
+        &sky,
+        &camera,
+        &settings,
+        &mut accumulator,
+        &adaptive,
+        |_, _| {
+            passes += 1;
+            Ok(())
+        },
+    )
+    .unwrap();
+    let samples = |x, y| accumulator.samples.get(x, y);
+
+    // The sky has no variance, its pixels stop as soon as they may
+    for y in 0..3 {
+        assert!((0..settings.width).all(|x| samples(x, y) == adaptive.min_samples));
+    }
+
+    // The shadow under the ball keeps sampling, some of it up to the maximum but no
+    // further, although the passes do not add up to it exactly
+    let shadow: u32 = (12..16)
+        .flat_map(|y| (6..18).map(move |x| (x, y)))
+        .map(|(x, y)| samples(x, y))
+        .sum();
+    assert!(shadow / (4 * 12) > 2 * adaptive.min_samples);
+    assert!(accumulator.samples.pixels().contains(&adaptive.max_samples));
+    assert!(accumulator
+        .samples
+        .pixels()
+        .iter()
+        .all(|&n| n <= adaptive.max_samples));
+    assert!(passes > 1);
+
+    // Nothing is left to do
+    let budget = next_budget(&accumulator, &adaptive, settings.region());
+    assert!(budget.pixels().iter().all(|&n| n == 0));
+
+    // The heatmap runs from blue for the fewest samples to red for the most
+    let heatmap = sample_heatmap(&accumulator, adaptive.max_samples);
+    let (sky, most) = (heatmap.get(12, 0), heatmap.get(12, 15));
+    assert!(sky.z > 0.0 && sky.x == 0.0);
+    assert!(most.x > 0.0 && most.z == 0.0);
+}
+
+#[test]
+fn adaptive_thresholds_apply_to_the_error_after_the_srgb_curve() {
+    // A hundred samples, half of them 0.08 and half 0.28, around a mean of 0.18
+    let mut accumulator = Accumulator::new(1, 1);
+    accumulator.samples.set(0, 0, 100);
+    accumulator.luminance.set(0, 0, 50.0 * 0.08 + 50.0 * 0.28);
+    accumulator
+        .sum_squares
+        .set(0, 0, 50.0 * 0.08 * 0.08 + 50.0 * 0.28 * 0.28);
+
+    // A standard error of 0.01005, about 5.6% of the mean, and the sRGB curve rises
+    // 1.196 times as fast as the luminance there
+    let error = pixel_error(&accumulator, 0, 0);
+    assert!((error - 0.01005 * 1.196).abs() < 1e-4);
+
+    let converged = |threshold| {
+        let adaptive = AdaptiveSettings {
+            threshold,
+            min_samples: 16,
+            max_samples: 1000,
+            pass_samples: 16,
+        };
+        let region = Tile {
+            x0: 0,
+            y0: 0,
+            x1: 1,
+            y1: 1,
+        };
+        next_budget(&accumulator, &adaptive, region).get(0, 0) == 0
+    };
+    assert!(converged(0.013));
+    assert!(!converged(0.011));
+}
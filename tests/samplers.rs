@@ -0,0 +1,72 @@
+use pathtracer::{MisHeuristic, Sampler, SamplerKind};
+
+const KINDS: [SamplerKind; 5] = [
+    SamplerKind::Independent,
+    SamplerKind::Stratified,
+    SamplerKind::Halton,
+    SamplerKind::Sobol,
+    SamplerKind::BlueNoise,
+];
+
+// The first few dimensions of every sample of a pixel
+fn dimensions(sampler: &mut dyn Sampler, pixel: (usize, usize), samples: u32) -> Vec<Vec<f32>> {
+    (0..samples)
+        .map(|index| {
+            sampler.start_sample(pixel, index);
+            let (a, b) = sampler.get_2d();
+            vec![a, b, sampler.get_1d(), sampler.get_1d()]
+        })
+        .collect()
+}
+
+#[test]
+fn samples_are_in_the_unit_interval_and_reproducible() {
+    for &kind in &KINDS {
+        let mut sampler = kind.create(1, 64);
+        let first = dimensions(sampler.as_mut(), (3, 5), 64);
+        let again = dimensions(sampler.as_mut(), (3, 5), 64);
+        let other = dimensions(sampler.as_mut(), (4, 5), 64);
+
+        assert!(first.iter().flatten().all(|v| (0.0..1.0).contains(v)));
+        assert_eq!(first, again, "{} is not reproducible", kind.name());
+        assert_ne!(first, other, "{} repeats across pixels", kind.name());
+    }
+}
+
+#[test]
+fn stratified_samplers_fill_every_stratum() {
+    for &kind in &[SamplerKind::Stratified, SamplerKind::Sobol] {
+        let mut sampler = kind.create(7, 16);
+        let samples = dimensions(sampler.as_mut(), (10, 2), 16);
+
+        // One sample in each of 16 intervals, and in each cell of a 4x4 grid
+        let mut intervals = [0; 16];
+        let mut cells = [0; 16];
+        for sample in &samples {
+            intervals[(sample[2] * 16.0) as usize] += 1;
+            cells[(sample[1] * 4.0) as usize * 4 + (sample[0] * 4.0) as usize] += 1;
+        }
+
+        assert_eq!(intervals, [1; 16], "{}", kind.name());
+        assert_eq!(cells, [1; 16], "{}", kind.name());
+    }
+}
+
+#[test]
+fn mis_weights_of_two_strategies_add_up_to_one() {
+    let pdfs = [(0.5, 2.0), (3.0, 3.0), (10.0, 0.01), (1.0, 0.0)];
+
+    for &mis in &[MisHeuristic::Balance, MisHeuristic::Power] {
+        for &(a, b) in &pdfs {
+            let sum = mis.weight(a, b) + mis.weight(b, a);
+            assert!((sum - 1.0).abs() < 1e-6, "{} {} {}", mis.name(), a, b);
+        }
+
+        // A strategy that cannot take the sample gets no weight
+        assert_eq!(mis.weight(0.0, 1.0), 0.0);
+        assert_eq!(mis.weight(f32::INFINITY, 1.0), 1.0);
+    }
+
+    // The power heuristic leans further towards the likelier strategy
+    assert!(MisHeuristic::Power.weight(4.0, 1.0) > MisHeuristic::Balance.weight(4.0, 1.0));
+}